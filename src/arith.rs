@@ -0,0 +1,396 @@
+use anyhow::{anyhow, bail, Result};
+
+use crate::vars::{get_var, is_name_char, is_name_start, set_var};
+
+/// Deepest chain of variables whose values are themselves expressions.
+const MAX_RECURSION: usize = 1024;
+
+// Ordered longest first so that tokenizing can stop at the first hit.
+const OPERATORS: &[&str] = &[
+    "<<=", ">>=", "**", "<<", ">>", "<=", ">=", "==", "!=", "&&", "||", "++", "--", "+=", "-=",
+    "*=", "/=", "%=", "&=", "^=", "|=", "+", "-", "*", "/", "%", "<", ">", "=", "!", "~", "&", "^",
+    "|", "?", ":", ",", "(", ")",
+];
+
+const ASSIGNMENT_OPERATORS: &[&str] = &[
+    "=", "+=", "-=", "*=", "/=", "%=", "<<=", ">>=", "&=", "^=", "|=",
+];
+
+// Binary operators below `**`, from loosest to tightest binding.
+const BINARY_OPERATORS: &[(&str, u8)] = &[
+    ("||", 1),
+    ("&&", 2),
+    ("|", 3),
+    ("^", 4),
+    ("&", 5),
+    ("==", 6),
+    ("!=", 6),
+    ("<", 7),
+    (">", 7),
+    ("<=", 7),
+    (">=", 7),
+    ("<<", 8),
+    (">>", 8),
+    ("+", 9),
+    ("-", 9),
+    ("*", 10),
+    ("/", 10),
+    ("%", 10),
+];
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Token {
+    Num(i64),
+    Name(String),
+    Op(&'static str),
+}
+
+#[derive(Debug)]
+enum Expr {
+    Num(i64),
+    Var(String),
+    Unary(&'static str, Box<Expr>),
+    Binary(&'static str, Box<Expr>, Box<Expr>),
+    Assign(&'static str, String, Box<Expr>),
+    IncDec {
+        name: String,
+        delta: i64,
+        prefix: bool,
+    },
+    Cond(Box<Expr>, Box<Expr>, Box<Expr>),
+}
+
+/// Evaluates an arithmetic expression with C-like semantics on 64-bit
+/// integers. Variables may be named without `$` and assigned to.
+pub fn evaluate(expr: &str) -> Result<i64> {
+    evaluate_nested(expr, 0).map_err(|e| anyhow!("{}: {}", expr.trim(), e))
+}
+
+fn evaluate_nested(expr: &str, depth: usize) -> Result<i64> {
+    if depth > MAX_RECURSION {
+        bail!("expression recursion level exceeded");
+    }
+    let tokens = tokenize(expr)?;
+    if tokens.is_empty() {
+        return Ok(0);
+    }
+    let mut parser = Parser { tokens, pos: 0 };
+    let expr = parser.parse_comma()?;
+    if let Some(token) = parser.tokens.get(parser.pos) {
+        bail!("syntax error in expression (error token is \"{}\")", token);
+    }
+    expr.eval(depth)
+}
+
+impl std::fmt::Display for Token {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Token::Num(n) => write!(f, "{}", n),
+            Token::Name(name) => write!(f, "{}", name),
+            Token::Op(op) => write!(f, "{}", op),
+        }
+    }
+}
+
+fn tokenize(expr: &str) -> Result<Vec<Token>> {
+    let mut tokens = vec![];
+    let mut rest = expr.trim_start();
+    while let Some(c) = rest.chars().next() {
+        let len = if c.is_ascii_digit() {
+            let len = rest
+                .find(|c: char| !(c.is_ascii_alphanumeric() || c == '#' || c == '@' || c == '_'))
+                .unwrap_or(rest.len());
+            tokens.push(Token::Num(parse_number(&rest[..len])?));
+            len
+        } else if is_name_start(c) {
+            let len = rest.find(|c| !is_name_char(c)).unwrap_or(rest.len());
+            tokens.push(Token::Name(rest[..len].to_string()));
+            len
+        } else if let Some(op) = OPERATORS.iter().find(|op| rest.starts_with(*op)) {
+            tokens.push(Token::Op(op));
+            op.len()
+        } else {
+            bail!(
+                "syntax error: operand expected (error token is \"{}\")",
+                rest
+            );
+        };
+        rest = rest[len..].trim_start();
+    }
+    Ok(tokens)
+}
+
+/// Parses decimal, octal (`017`), hexadecimal (`0x1f`) and `base#digits`
+/// constants.
+fn parse_number(s: &str) -> Result<i64> {
+    let invalid = || anyhow!("value too great for base (error token is \"{}\")", s);
+    let (base, digits) = match s.split_once('#') {
+        Some((base, digits)) => match base.parse::<u32>() {
+            Ok(base @ 2..=64) => (base, digits),
+            _ => bail!("invalid arithmetic base (error token is \"{}\")", s),
+        },
+        None if s.starts_with("0x") || s.starts_with("0X") => (16, &s[2..]),
+        None if s.len() > 1 && s.starts_with('0') => (8, &s[1..]),
+        None => (10, s),
+    };
+    if digits.is_empty() {
+        return Err(invalid());
+    }
+    digits.chars().try_fold(0i64, |acc, c| {
+        let digit = match c {
+            '0'..='9' => c as u32 - '0' as u32,
+            'a'..='z' => c as u32 - 'a' as u32 + 10,
+            'A'..='Z' if base <= 36 => c as u32 - 'A' as u32 + 10,
+            'A'..='Z' => c as u32 - 'A' as u32 + 36,
+            '@' => 62,
+            '_' => 63,
+            _ => return Err(invalid()),
+        };
+        if digit >= base {
+            return Err(invalid());
+        }
+        Ok(acc.wrapping_mul(base as i64).wrapping_add(digit as i64))
+    })
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek_op(&self) -> Option<&'static str> {
+        match self.tokens.get(self.pos) {
+            Some(Token::Op(op)) => Some(op),
+            _ => None,
+        }
+    }
+
+    fn expect(&mut self, op: &str) -> Result<()> {
+        match self.tokens.get(self.pos) {
+            Some(Token::Op(found)) if *found == op => {
+                self.pos += 1;
+                Ok(())
+            }
+            Some(token) => bail!("syntax error in expression (error token is \"{}\")", token),
+            None => bail!("syntax error: `{}' expected", op),
+        }
+    }
+
+    fn parse_comma(&mut self) -> Result<Expr> {
+        let mut expr = self.parse_assign()?;
+        while self.peek_op() == Some(",") {
+            self.pos += 1;
+            expr = Expr::Binary(",", Box::new(expr), Box::new(self.parse_assign()?));
+        }
+        Ok(expr)
+    }
+
+    fn parse_assign(&mut self) -> Result<Expr> {
+        if let (Some(Token::Name(name)), Some(Token::Op(op))) =
+            (self.tokens.get(self.pos), self.tokens.get(self.pos + 1))
+        {
+            if ASSIGNMENT_OPERATORS.contains(op) {
+                let (name, op) = (name.clone(), *op);
+                self.pos += 2;
+                return Ok(Expr::Assign(op, name, Box::new(self.parse_assign()?)));
+            }
+        }
+        self.parse_conditional()
+    }
+
+    fn parse_conditional(&mut self) -> Result<Expr> {
+        let cond = self.parse_binary(1)?;
+        if self.peek_op() != Some("?") {
+            return Ok(cond);
+        }
+        self.pos += 1;
+        let then = self.parse_comma()?;
+        self.expect(":")?;
+        let otherwise = self.parse_assign()?;
+        Ok(Expr::Cond(
+            Box::new(cond),
+            Box::new(then),
+            Box::new(otherwise),
+        ))
+    }
+
+    fn parse_binary(&mut self, min_prec: u8) -> Result<Expr> {
+        let mut lhs = self.parse_power()?;
+        while let Some((op, prec)) = self.peek_op().and_then(|op| {
+            BINARY_OPERATORS
+                .iter()
+                .find(|(o, prec)| *o == op && *prec >= min_prec)
+                .copied()
+        }) {
+            self.pos += 1;
+            let rhs = self.parse_binary(prec + 1)?;
+            lhs = Expr::Binary(op, Box::new(lhs), Box::new(rhs));
+        }
+        Ok(lhs)
+    }
+
+    fn parse_power(&mut self) -> Result<Expr> {
+        let base = self.parse_unary()?;
+        if self.peek_op() != Some("**") {
+            return Ok(base);
+        }
+        self.pos += 1;
+        Ok(Expr::Binary(
+            "**",
+            Box::new(base),
+            Box::new(self.parse_power()?),
+        ))
+    }
+
+    fn parse_unary(&mut self) -> Result<Expr> {
+        match (self.peek_op(), self.tokens.get(self.pos + 1)) {
+            (Some(op @ ("++" | "--")), Some(Token::Name(name))) => {
+                let name = name.clone();
+                self.pos += 2;
+                let delta = if op == "++" { 1 } else { -1 };
+                Ok(Expr::IncDec {
+                    name,
+                    delta,
+                    prefix: true,
+                })
+            }
+            // Without a variable to apply to, `++` and `--` are two signs.
+            (Some(op @ ("++" | "--")), _) => {
+                self.pos += 1;
+                let sign = &op[..1];
+                let operand = Expr::Unary(sign, Box::new(self.parse_unary()?));
+                Ok(Expr::Unary(sign, Box::new(operand)))
+            }
+            (Some(op @ ("-" | "+" | "!" | "~")), _) => {
+                self.pos += 1;
+                Ok(Expr::Unary(op, Box::new(self.parse_unary()?)))
+            }
+            _ => self.parse_postfix(),
+        }
+    }
+
+    fn parse_postfix(&mut self) -> Result<Expr> {
+        match self.tokens.get(self.pos).cloned() {
+            Some(Token::Num(n)) => {
+                self.pos += 1;
+                Ok(Expr::Num(n))
+            }
+            Some(Token::Name(name)) => {
+                self.pos += 1;
+                match self.peek_op() {
+                    Some(op @ ("++" | "--")) => {
+                        self.pos += 1;
+                        let delta = if op == "++" { 1 } else { -1 };
+                        Ok(Expr::IncDec {
+                            name,
+                            delta,
+                            prefix: false,
+                        })
+                    }
+                    _ => Ok(Expr::Var(name)),
+                }
+            }
+            Some(Token::Op("(")) => {
+                self.pos += 1;
+                let expr = self.parse_comma()?;
+                self.expect(")")?;
+                Ok(expr)
+            }
+            Some(token) => bail!(
+                "syntax error: operand expected (error token is \"{}\")",
+                token
+            ),
+            None => bail!("syntax error: operand expected"),
+        }
+    }
+}
+
+impl Expr {
+    fn eval(&self, depth: usize) -> Result<i64> {
+        Ok(match self {
+            Expr::Num(n) => *n,
+            Expr::Var(name) => var_value(name, depth)?,
+            Expr::Unary(op, operand) => {
+                let value = operand.eval(depth)?;
+                match *op {
+                    "-" => value.wrapping_neg(),
+                    "!" => (value == 0).into(),
+                    "~" => !value,
+                    _ => value,
+                }
+            }
+            Expr::Binary("&&", lhs, rhs) => (lhs.eval(depth)? != 0 && rhs.eval(depth)? != 0).into(),
+            Expr::Binary("||", lhs, rhs) => (lhs.eval(depth)? != 0 || rhs.eval(depth)? != 0).into(),
+            Expr::Binary(op, lhs, rhs) => {
+                let lhs = lhs.eval(depth)?;
+                apply_binary(op, lhs, rhs.eval(depth)?)?
+            }
+            Expr::Assign(op, name, value) => {
+                let value = value.eval(depth)?;
+                let value = match op.strip_suffix('=').filter(|op| !op.is_empty()) {
+                    Some(op) => apply_binary(op, var_value(name, depth)?, value)?,
+                    None => value,
+                };
+                set_var(name, &value.to_string())?;
+                value
+            }
+            Expr::IncDec {
+                name,
+                delta,
+                prefix,
+            } => {
+                let old = var_value(name, depth)?;
+                let new = old.wrapping_add(*delta);
+                set_var(name, &new.to_string())?;
+                if *prefix {
+                    new
+                } else {
+                    old
+                }
+            }
+            Expr::Cond(cond, then, otherwise) => {
+                if cond.eval(depth)? != 0 {
+                    then.eval(depth)?
+                } else {
+                    otherwise.eval(depth)?
+                }
+            }
+        })
+    }
+}
+
+/// A variable's value is itself evaluated as an expression; unset and empty
+/// variables count as zero.
+fn var_value(name: &str, depth: usize) -> Result<i64> {
+    match get_var(name) {
+        Some(value) if !value.trim().is_empty() => evaluate_nested(&value, depth + 1),
+        _ => Ok(0),
+    }
+}
+
+fn apply_binary(op: &str, lhs: i64, rhs: i64) -> Result<i64> {
+    Ok(match op {
+        "," => rhs,
+        "|" => lhs | rhs,
+        "^" => lhs ^ rhs,
+        "&" => lhs & rhs,
+        "==" => (lhs == rhs).into(),
+        "!=" => (lhs != rhs).into(),
+        "<" => (lhs < rhs).into(),
+        ">" => (lhs > rhs).into(),
+        "<=" => (lhs <= rhs).into(),
+        ">=" => (lhs >= rhs).into(),
+        "<<" => lhs.wrapping_shl(rhs as u32),
+        ">>" => lhs.wrapping_shr(rhs as u32),
+        "+" => lhs.wrapping_add(rhs),
+        "-" => lhs.wrapping_sub(rhs),
+        "*" => lhs.wrapping_mul(rhs),
+        "/" | "%" if rhs == 0 => bail!("division by 0"),
+        "/" => lhs.wrapping_div(rhs),
+        "%" => lhs.wrapping_rem(rhs),
+        "**" if rhs < 0 => bail!("exponent less than 0"),
+        "**" => lhs.wrapping_pow(rhs.min(u32::MAX as i64) as u32),
+        _ => unreachable!("unknown arithmetic operator {}", op),
+    })
+}
@@ -0,0 +1,188 @@
+use anyhow::{bail, Result};
+
+use crate::lexer::{BS, DQ, SQ};
+
+/// Most words a single `{x..y}` sequence may stand for.
+const MAX_SEQUENCE: u64 = 1 << 20;
+
+/// Expands `{a,b}` lists and `{x..y[..step]}` sequences in a word as produced
+/// by the lexer, before any other expansion. Braces that are quoted, escaped
+/// or part of `${...}` are left alone, as are braces that form neither a
+/// list nor a sequence. Fails on a sequence longer than `MAX_SEQUENCE`.
+pub fn expand_braces(word: &str) -> Result<Vec<String>> {
+    let chars: Vec<char> = word.chars().collect();
+    let mut pos = 0;
+    while pos < chars.len() {
+        if let Some(next) = skip_quoted(&chars, pos) {
+            pos = next;
+            continue;
+        }
+        if chars[pos] == '{' {
+            if let Some((close, items)) = parse_brace(&chars, pos)? {
+                let prefix: String = chars[..pos].iter().collect();
+                let suffix: String = chars[close + 1..].iter().collect();
+                let suffixes = expand_braces(&suffix)?;
+                let mut words = vec![];
+                for item in &items {
+                    for item in expand_braces(item)? {
+                        for suffix in &suffixes {
+                            words.push(format!("{}{}{}", prefix, item, suffix));
+                        }
+                    }
+                }
+                return Ok(words);
+            }
+        }
+        pos += 1;
+    }
+    Ok(vec![word.to_string()])
+}
+
+/// Returns the position just past the quoted text, `$`/backquote expansion
+/// or process substitution starting at `pos`, or `None` if an ordinary
+/// character is there.
+fn skip_quoted(chars: &[char], pos: usize) -> Option<usize> {
+    let end = match (chars[pos], chars.get(pos + 1)) {
+        (BS, _) => pos + 2,
+        (SQ, _) => find_close(chars, pos + 1, SQ, false),
+        (DQ, _) => find_close(chars, pos + 1, DQ, true),
+        ('`', _) => find_close(chars, pos + 1, '`', true),
+        ('$', Some('{')) => skip_group(chars, pos + 2, '{', '}'),
+        ('$', Some('(')) => skip_group(chars, pos + 2, '(', ')'),
+        ('<' | '>', Some('(')) => skip_group(chars, pos + 2, '(', ')'),
+        _ => return None,
+    };
+    Some(end.min(chars.len()))
+}
+
+fn find_close(chars: &[char], mut pos: usize, close: char, escapes: bool) -> usize {
+    while let Some(&c) = chars.get(pos) {
+        match c {
+            BS if escapes => pos += 2,
+            c if c == close => return pos + 1,
+            _ => pos += 1,
+        }
+    }
+    pos
+}
+
+fn skip_group(chars: &[char], mut pos: usize, open: char, close: char) -> usize {
+    let mut depth = 0;
+    while pos < chars.len() {
+        if let Some(next) = skip_quoted(chars, pos) {
+            pos = next;
+            continue;
+        }
+        match chars[pos] {
+            c if c == open => depth += 1,
+            c if c == close && depth == 0 => return pos + 1,
+            c if c == close => depth -= 1,
+            _ => {}
+        }
+        pos += 1;
+    }
+    pos
+}
+
+/// Parses the brace expression opening at `open`. Returns the position of
+/// its closing brace and the words it stands for.
+fn parse_brace(chars: &[char], open: usize) -> Result<Option<(usize, Vec<String>)>> {
+    let mut depth = 0;
+    let mut commas = vec![];
+    let mut pos = open + 1;
+    while pos < chars.len() {
+        if let Some(next) = skip_quoted(chars, pos) {
+            pos = next;
+            continue;
+        }
+        match chars[pos] {
+            '{' => depth += 1,
+            '}' if depth == 0 => break,
+            '}' => depth -= 1,
+            ',' if depth == 0 => commas.push(pos),
+            _ => {}
+        }
+        pos += 1;
+    }
+    let close = pos;
+    if close >= chars.len() {
+        return Ok(None);
+    }
+    if commas.is_empty() {
+        let body: String = chars[open + 1..close].iter().collect();
+        return Ok(sequence(&body)?.map(|items| (close, items)));
+    }
+    let mut items = vec![];
+    let mut start = open + 1;
+    for end in commas.into_iter().chain(std::iter::once(close)) {
+        items.push(chars[start..end].iter().collect());
+        start = end + 1;
+    }
+    Ok(Some((close, items)))
+}
+
+/// Expands the body of a `{x..y[..step]}` sequence of integers or of single
+/// letters. Integers are zero-padded to the same width when either end is
+/// written with a leading zero.
+fn sequence(body: &str) -> Result<Option<Vec<String>>> {
+    let parts: Vec<&str> = body.split("..").collect();
+    let (start, end, step) = match parts[..] {
+        [start, end] => (start, end, 1),
+        [start, end, step] => match step.parse::<i64>() {
+            Ok(step) => (start, end, step),
+            Err(_) => return Ok(None),
+        },
+        _ => return Ok(None),
+    };
+    let step = step.unsigned_abs().max(1);
+    if let (Ok(first), Ok(last)) = (start.parse::<i64>(), end.parse::<i64>()) {
+        let width = if is_padded(start) || is_padded(end) {
+            start.len().max(end.len())
+        } else {
+            0
+        };
+        let Some(range) = range(first, last, step) else {
+            bail!("{{{}}}: brace sequence too long", body);
+        };
+        return Ok(Some(
+            range
+                .map(|n| format!("{:0width$}", n, width = width))
+                .collect(),
+        ));
+    }
+    Ok(match (single_letter(start), single_letter(end)) {
+        (Some(first), Some(last)) => range(first as i64, last as i64, step)
+            .map(|range| range.map(|c| (c as u8 as char).to_string()).collect()),
+        _ => None,
+    })
+}
+
+fn is_padded(n: &str) -> bool {
+    let digits = n.strip_prefix('-').unwrap_or(n);
+    digits.len() > 1 && digits.starts_with('0')
+}
+
+fn single_letter(s: &str) -> Option<char> {
+    let mut chars = s.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) if c.is_ascii_alphabetic() => Some(c),
+        _ => None,
+    }
+}
+
+/// Counts from `first` towards `last` in steps of `step`, in either direction.
+/// Returns `None` if that would take more than `MAX_SEQUENCE` numbers.
+fn range(first: i64, last: i64, step: u64) -> Option<impl Iterator<Item = i64>> {
+    let count = first.abs_diff(last) / step;
+    if count >= MAX_SEQUENCE {
+        return None;
+    }
+    // Every number lies between `first` and `last`, but `i * step` may not
+    // fit in an `i64` on its own.
+    let step = if first <= last {
+        i128::from(step)
+    } else {
+        -i128::from(step)
+    };
+    Some((0..=count).map(move |i| (i128::from(first) + i128::from(i) * step) as i64))
+}
@@ -1,14 +1,24 @@
-use anyhow::{Context, Result};
+use anyhow::{anyhow, bail, Context, Result};
 use std::{
-    env,
+    env, fmt,
     fs::metadata,
-    os::unix::fs::PermissionsExt,
+    os::unix::{fs::PermissionsExt, process::CommandExt},
     path::{Path, PathBuf},
+    process::Command as ProcessCommand,
 };
 
 use crate::{
+    arith::evaluate,
     context::CommandContext,
+    exec::{call_function, loop_depth},
+    functions::{in_function, is_function},
     history::{write_history_on_exit, CommandHistory},
+    jobs::{list_jobs, wait_all_jobs, wait_job},
+    options::{option_for_flag, set_option, set_options, set_shopt, shopt_options},
+    vars::{
+        all_vars, export_var, exported_env, get_var, is_valid_name, last_status, set_readonly,
+        set_var, unset_var, Variable,
+    },
 };
 
 #[derive(Clone, Debug)]
@@ -20,6 +30,20 @@ pub enum Command {
     Pwd,
     Cd,
     History,
+    Export,
+    Unset,
+    Readonly,
+    Let,
+    Shopt,
+    Read,
+    Set,
+    Exec,
+    Jobs,
+    Wait,
+    Return,
+    Break,
+    Continue,
+    Function(String),
     Invalid,
 }
 
@@ -32,6 +56,20 @@ impl From<&str> for Command {
             "pwd" => Command::Pwd,
             "cd" => Command::Cd,
             "history" => Command::History,
+            "export" => Command::Export,
+            "unset" => Command::Unset,
+            "readonly" => Command::Readonly,
+            "let" => Command::Let,
+            "shopt" => Command::Shopt,
+            "read" => Command::Read,
+            "set" => Command::Set,
+            "exec" => Command::Exec,
+            "jobs" => Command::Jobs,
+            "wait" => Command::Wait,
+            "return" => Command::Return,
+            "break" => Command::Break,
+            "continue" => Command::Continue,
+            _ if is_function(command) => Command::Function(command.to_string()),
             _ => try_get_executable_path(command)
                 .map(|path| Command::Executable {
                     name: command.to_string(),
@@ -42,37 +80,160 @@ impl From<&str> for Command {
     }
 }
 
-pub fn handle_command(ctx: &mut CommandContext) -> Result<()> {
-    match &ctx.command.clone() {
-        Command::Echo => ctx.writeln(ctx.args.join(" "))?,
+impl Command {
+    /// Whether this is one of the special builtins whose prefix assignments
+    /// stay in effect after it finishes, as in `FOO=1 export FOO`.
+    pub fn is_special(&self) -> bool {
+        matches!(
+            self,
+            Command::Exit
+                | Command::Export
+                | Command::Unset
+                | Command::Readonly
+                | Command::Set
+                | Command::Exec
+                | Command::Return
+                | Command::Break
+                | Command::Continue
+        )
+    }
+}
+
+/// Returned as an error by `exit` so that it unwinds only the list it ends:
+/// subshells and pipeline stages turn it into their status, and only the
+/// shell's top level actually exits.
+#[derive(Debug)]
+pub struct ExitRequest(pub i32);
+
+impl fmt::Display for ExitRequest {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "exit {}", self.0)
+    }
+}
+
+impl std::error::Error for ExitRequest {}
+
+/// Returned as an error by `return` to unwind to the function call it ends.
+#[derive(Debug)]
+pub struct ReturnRequest(pub i32);
+
+impl fmt::Display for ReturnRequest {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "return {}", self.0)
+    }
+}
+
+impl std::error::Error for ReturnRequest {}
+
+/// Returned as an error by `break` and `continue` to unwind to the loop they
+/// name, counted outward from the innermost one.
+#[derive(Debug)]
+pub enum LoopControl {
+    Break(usize),
+    Continue(usize),
+}
+
+impl fmt::Display for LoopControl {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LoopControl::Break(n) => write!(f, "break {}", n),
+            LoopControl::Continue(n) => write!(f, "continue {}", n),
+        }
+    }
+}
+
+impl std::error::Error for LoopControl {}
+
+/// Runs a builtin and returns its exit status.
+pub fn handle_command(ctx: &mut CommandContext) -> Result<i32> {
+    let status = match &ctx.command.clone() {
+        Command::Echo => {
+            ctx.writeln(ctx.args.join(" "))?;
+            0
+        }
         Command::Type => type_command(ctx.args.first().unwrap_or(&String::new()), ctx)?,
-        Command::Pwd => ctx.writeln(env::current_dir()?.display())?,
-        Command::Cd => {
-            if change_directory(ctx.args.as_slice()).is_err() {
-                ctx.writeln(format_args!(
+        Command::Pwd => {
+            ctx.writeln(env::current_dir()?.display())?;
+            0
+        }
+        Command::Cd => match change_directory(ctx.args.as_slice()) {
+            Ok(()) => 0,
+            Err(_) => {
+                ctx.ewriteln(format_args!(
                     "cd: {}: No such file or directory",
                     ctx.args.first().unwrap(),
                 ))?;
+                1
             }
+        },
+        Command::Invalid => {
+            ctx.ewriteln(format_args!("{}: command not found", ctx.command_str))?;
+            127
         }
-        Command::Invalid => ctx.ewriteln(format_args!("{}: command not found", ctx.command_str))?,
         Command::Exit => {
             let code = ctx
                 .args
                 .first()
                 .and_then(|x| x.parse::<i32>().ok())
-                .unwrap_or(0);
-            write_history_on_exit();
-            std::process::exit(code)
+                .unwrap_or_else(last_status);
+            return Err(ExitRequest(code).into());
         }
         Command::History => CommandHistory::handle_command(ctx),
-        Command::Executable { .. } => {}
-    }
-    Ok(())
+        Command::Export => declare_command(ctx, export_var, |var| var.exported, "-x")?,
+        Command::Readonly => declare_command(ctx, set_readonly, |var| var.readonly, "-r")?,
+        Command::Unset => {
+            let mut status = 0;
+            for name in ctx.args.iter().filter(|arg| *arg != "-v") {
+                if let Err(e) = unset_var(name) {
+                    ctx.ewriteln(format_args!("unset: {}", e))?;
+                    status = 1;
+                }
+            }
+            status
+        }
+        Command::Let => {
+            if ctx.args.is_empty() {
+                bail!("let: expression expected");
+            }
+            let mut value = 0;
+            for arg in &ctx.args {
+                value = evaluate(arg).map_err(|e| anyhow!("let: {}", e))?;
+            }
+            i32::from(value == 0)
+        }
+        Command::Shopt => shopt_command(ctx)?,
+        Command::Read => read_command(ctx)?,
+        Command::Set => set_command(ctx)?,
+        Command::Exec => exec_command(ctx)?,
+        Command::Jobs => {
+            for report in list_jobs() {
+                ctx.writeln(report)?;
+            }
+            0
+        }
+        Command::Wait => wait_command(ctx)?,
+        Command::Return => {
+            if !in_function() {
+                bail!("return: can only `return' from a function");
+            }
+            let code = match ctx.args.first() {
+                Some(arg) => arg
+                    .parse::<i32>()
+                    .map_err(|_| anyhow!("return: {}: numeric argument required", arg))?,
+                None => last_status(),
+            };
+            return Err(ReturnRequest(code).into());
+        }
+        Command::Break => return Err(loop_control(ctx, LoopControl::Break)?.into()),
+        Command::Continue => return Err(loop_control(ctx, LoopControl::Continue)?.into()),
+        Command::Function(name) => call_function(name, ctx)?,
+        Command::Executable { .. } => 0,
+    };
+    Ok(status)
 }
 
 fn try_get_executable_path(command: &str) -> Option<PathBuf> {
-    env::var("PATH").ok()?.split(':').find_map(|dir| {
+    get_var("PATH")?.split(':').find_map(|dir| {
         let path = Path::new(dir).join(command);
         is_executable(&path).then_some(path)
     })
@@ -82,29 +243,317 @@ pub fn is_executable(path: &Path) -> bool {
     metadata(path).is_ok_and(|m| m.is_file() && m.permissions().mode() & 0o111 != 0)
 }
 
-fn type_command(cmd: &str, ctx: &CommandContext) -> Result<()> {
+fn type_command(cmd: &str, ctx: &CommandContext) -> Result<i32> {
     match Command::from(cmd) {
-        Command::Invalid => ctx.writeln(format_args!("{}: not found", cmd)),
+        Command::Invalid => {
+            ctx.writeln(format_args!("{}: not found", cmd))?;
+            return Ok(1);
+        }
         Command::Executable {
             name: _,
             full_path: path,
-        } => ctx.writeln(format_args!("{} is {}", cmd, path.display())),
-        _ => ctx.writeln(format_args!("{} is a shell builtin", cmd)),
+        } => ctx.writeln(format_args!("{} is {}", cmd, path.display()))?,
+        Command::Function(_) => ctx.writeln(format_args!("{} is a function", cmd))?,
+        _ => ctx.writeln(format_args!("{} is a shell builtin", cmd))?,
+    }
+    Ok(0)
+}
+
+/// `read [-r] [name...]`: reads a line of input and splits it on whitespace
+/// into the named variables, the last of which takes the rest of the line.
+/// Without names the line goes into `REPLY`. Unless `-r` is given, a
+/// backslash escapes the next character and continues the line at its end.
+fn read_command(ctx: &CommandContext) -> Result<i32> {
+    let raw = ctx.args.first().is_some_and(|arg| arg == "-r");
+    let names = &ctx.args[usize::from(raw)..];
+    if let Some(name) = names.iter().find(|name| !is_valid_name(name)) {
+        ctx.ewriteln(format_args!("read: `{}': not a valid identifier", name))?;
+        return Ok(1);
+    }
+    // Each character is paired with whether it was escaped.
+    let mut chars: Vec<(char, bool)> = vec![];
+    let mut eof = true;
+    while let Some(line) = ctx.read_line()? {
+        eof = false;
+        let mut continued = false;
+        let mut line = line.chars();
+        while let Some(c) = line.next() {
+            match c {
+                '\\' if !raw => match line.next() {
+                    Some(c) => chars.push((c, true)),
+                    None => continued = true,
+                },
+                c => chars.push((c, false)),
+            }
+        }
+        if !continued {
+            break;
+        }
+    }
+    let is_separator = |&(c, escaped): &(char, bool)| !escaped && c.is_whitespace();
+    let to_string = |chars: &[(char, bool)]| chars.iter().map(|(c, _)| c).collect::<String>();
+    if names.is_empty() {
+        set_var("REPLY", &to_string(&chars))?;
+    }
+    let mut rest = chars.as_slice();
+    for (i, name) in names.iter().enumerate() {
+        let start = rest
+            .iter()
+            .position(|c| !is_separator(c))
+            .unwrap_or(rest.len());
+        rest = &rest[start..];
+        let value = if i + 1 == names.len() {
+            let end = rest
+                .iter()
+                .rposition(|c| !is_separator(c))
+                .map_or(0, |end| end + 1);
+            &rest[..end]
+        } else {
+            let end = rest.iter().position(is_separator).unwrap_or(rest.len());
+            let value = &rest[..end];
+            rest = &rest[end..];
+            value
+        };
+        set_var(name, &to_string(value))?;
+    }
+    Ok(i32::from(eof))
+}
+
+/// `shopt [-squ] [name...]`: sets (`-s`) or unsets (`-u`) the named options,
+/// or reports their state. With `-q` nothing is printed and only the status
+/// tells whether all the named options are enabled.
+fn shopt_command(ctx: &CommandContext) -> Result<i32> {
+    let (mut enable, mut quiet) = (None, false);
+    let mut names = vec![];
+    for arg in &ctx.args {
+        match arg.strip_prefix('-') {
+            Some(flags) if names.is_empty() && !flags.is_empty() => {
+                for flag in flags.chars() {
+                    match flag {
+                        's' => enable = Some(true),
+                        'u' => enable = Some(false),
+                        'q' => quiet = true,
+                        _ => bail!("shopt: -{}: invalid option", flag),
+                    }
+                }
+            }
+            _ => names.push(arg.as_str()),
+        }
+    }
+    let options = shopt_options();
+    if names.is_empty() {
+        for (name, enabled) in options {
+            let listed = match enable {
+                Some(enable) => enable == enabled,
+                None => true,
+            };
+            if listed && !quiet {
+                ctx.writeln(format_args!("{:<15}\t{}", name, on_off(enabled)))?;
+            }
+        }
+        return Ok(0);
+    }
+    let mut status = 0;
+    for name in names {
+        let result = match enable {
+            Some(enabled) => set_shopt(name, enabled).map(|()| true),
+            None => options
+                .iter()
+                .find(|(option, _)| *option == name)
+                .map(|(_, enabled)| *enabled)
+                .ok_or_else(|| anyhow!("{}: invalid shell option name", name)),
+        };
+        match result {
+            Ok(enabled) => {
+                if enable.is_none() && !quiet {
+                    ctx.writeln(format_args!("{:<15}\t{}", name, on_off(enabled)))?;
+                }
+                if !enabled {
+                    status = 1;
+                }
+            }
+            Err(e) => {
+                ctx.ewriteln(format_args!("shopt: {}", e))?;
+                status = 1;
+            }
+        }
+    }
+    Ok(status)
+}
+
+/// `exec [command [arg...]]`: replaces the shell with the command, or
+/// without one keeps the command's redirections for the rest of the session.
+fn exec_command(ctx: &CommandContext) -> Result<i32> {
+    let Some((name, args)) = ctx.args.split_first() else {
+        ctx.io.borrow().keep_redirections()?;
+        return Ok(0);
+    };
+    if try_get_executable_path(name).is_none() {
+        ctx.ewriteln(format_args!("exec: {}: not found", name))?;
+        return Ok(127);
     }
+    let mut command = ProcessCommand::new(name);
+    command
+        .args(args)
+        .env_clear()
+        .envs(exported_env())
+        .envs(ctx.env.iter().cloned());
+    ctx.io.borrow().configure(&mut command)?;
+    write_history_on_exit();
+    let e = command.exec();
+    bail!("exec: {}: {}", name, e)
 }
 
+/// `break [n]` and `continue [n]`: the request to unwind to the nth
+/// enclosing loop, or to the outermost if there are fewer.
+fn loop_control(ctx: &CommandContext, request: fn(usize) -> LoopControl) -> Result<LoopControl> {
+    let depth = loop_depth();
+    if depth == 0 {
+        bail!(
+            "{}: only meaningful in a `for', `while', or `until' loop",
+            ctx.command_str
+        );
+    }
+    let count = match ctx.args.first() {
+        Some(arg) => match arg.parse::<usize>() {
+            Ok(count) if count > 0 => count,
+            _ => bail!("{}: {}: loop count out of range", ctx.command_str, arg),
+        },
+        None => 1,
+    };
+    Ok(request(count.min(depth)))
+}
+
+/// `wait [%n|pid...]`: waits for the given jobs and returns the status of
+/// the last, or without arguments waits for every job and returns 0.
+fn wait_command(ctx: &CommandContext) -> Result<i32> {
+    if ctx.args.is_empty() {
+        wait_all_jobs();
+        return Ok(0);
+    }
+    let mut status = 0;
+    for spec in &ctx.args {
+        status = match wait_job(spec) {
+            Ok(status) => status,
+            Err(e) => {
+                ctx.ewriteln(format_args!("wait: {}", e))?;
+                127
+            }
+        };
+    }
+    Ok(status)
+}
+
+/// `set [-+o name] [-+C]`: enables (`-`) or disables (`+`) shell options,
+/// either by name after `o` or by their single-letter flags. `-o` or `+o`
+/// without a name lists the options, and `set` alone lists the variables.
+fn set_command(ctx: &CommandContext) -> Result<i32> {
+    if ctx.args.is_empty() {
+        for (name, var) in all_vars() {
+            ctx.writeln(format_args!("{}={}", name, var.value))?;
+        }
+        return Ok(0);
+    }
+    let mut args = ctx.args.iter();
+    while let Some(arg) = args.next() {
+        let (enable, flags) = match arg.split_at(arg.len().min(1)) {
+            ("-", flags) if !flags.is_empty() => (true, flags),
+            ("+", flags) if !flags.is_empty() => (false, flags),
+            _ => bail!("set: positional parameters are not supported"),
+        };
+        for flag in flags.chars() {
+            let name = match flag {
+                'o' => match args.next() {
+                    Some(name) => name.as_str(),
+                    None => {
+                        list_set_options(ctx, enable)?;
+                        continue;
+                    }
+                },
+                flag => match option_for_flag(flag) {
+                    Some(name) => name,
+                    None => bail!("set: {}{}: invalid option", arg.split_at(1).0, flag),
+                },
+            };
+            set_option(name, enable).map_err(|e| anyhow!("set: {}", e))?;
+        }
+    }
+    Ok(0)
+}
+
+/// Lists the options of `set`, as a table for `set -o` or as the commands
+/// that restore them for `set +o`.
+fn list_set_options(ctx: &CommandContext, table: bool) -> Result<()> {
+    for (name, enabled) in set_options() {
+        if table {
+            ctx.writeln(format_args!("{:<15}\t{}", name, on_off(enabled)))?;
+        } else {
+            let sign = if enabled { '-' } else { '+' };
+            ctx.writeln(format_args!("set {}o {}", sign, name))?;
+        }
+    }
+    Ok(())
+}
+
+fn on_off(enabled: bool) -> &'static str {
+    if enabled {
+        "on"
+    } else {
+        "off"
+    }
+}
+
+/// Shared implementation of `export` and `readonly`: marks each `NAME` or
+/// `NAME=value` argument, or lists the marked variables when given none.
+fn declare_command(
+    ctx: &CommandContext,
+    mark: fn(&str, Option<&str>) -> Result<()>,
+    is_marked: fn(&Variable) -> bool,
+    flag: &str,
+) -> Result<i32> {
+    let names: Vec<&String> = ctx.args.iter().filter(|arg| *arg != "-p").collect();
+    if names.is_empty() {
+        for (name, var) in all_vars().iter().filter(|(_, var)| is_marked(var)) {
+            ctx.writeln(format_args!("declare {} {}=\"{}\"", flag, name, var.value))?;
+        }
+        return Ok(0);
+    }
+    let mut status = 0;
+    for arg in names {
+        let (name, value) = match arg.split_once('=') {
+            Some((name, value)) => (name, Some(value)),
+            None => (arg.as_str(), None),
+        };
+        let result = if is_valid_name(name) {
+            mark(name, value)
+        } else {
+            Err(anyhow!("`{}': not a valid identifier", arg))
+        };
+        if let Err(e) = result {
+            ctx.ewriteln(format_args!("{}: {}", ctx.command_str, e))?;
+            status = 1;
+        }
+    }
+    Ok(status)
+}
+
+/// Changes the working directory and records the old and new ones in
+/// `OLDPWD` and `PWD`.
 fn change_directory(args: &[String]) -> Result<()> {
-    env::set_current_dir(match args.first() {
-        Some(arg) if arg != "~" => build_path(arg)?,
-        _ => get_home_dir()?,
-    })
-    .context("Failed to change directory")
+    let path = match args.first() {
+        Some(arg) => build_path(arg)?,
+        None => get_home_dir()?,
+    };
+    let old = get_current_dir()?;
+    env::set_current_dir(&path).context("Failed to change directory")?;
+    set_var("OLDPWD", &old.to_string_lossy())?;
+    set_var("PWD", &path.to_string_lossy())?;
+    Ok(())
 }
 
 fn build_path(arg: &str) -> Result<PathBuf> {
     let mut parts = arg.split('/');
     let init = match parts.next() {
-        Some("~") => get_home_dir()?,
         Some(".") => get_current_dir()?,
         Some("") => PathBuf::from("/"),
         Some("..") => get_current_dir()?.parent().unwrap().to_path_buf(),
@@ -125,7 +574,7 @@ fn build_path(arg: &str) -> Result<PathBuf> {
 }
 
 fn get_home_dir() -> Result<PathBuf> {
-    env::var("HOME")
+    get_var("HOME")
         .map(PathBuf::from)
         .context("Failed to get home directory")
 }
@@ -1,12 +1,33 @@
-use crate::{args::parse_args, command::Command};
-use anyhow::{bail, Result};
+use crate::{
+    command::Command,
+    exec::{fork_shell, wait_forked},
+    expand::{
+        expand_assignment, expand_here_doc, expand_redirect_target, expand_single, expand_word,
+    },
+    lexer::Operator,
+    options::option_enabled,
+    parser::{Redirect, SimpleCommand},
+    vars::set_var,
+};
+use anyhow::{anyhow, bail, Result};
+use once_cell::sync::Lazy;
 use os_pipe::{PipeReader, PipeWriter};
 use std::{
     cell::RefCell,
+    collections::{BTreeMap, BTreeSet},
     fmt::Display,
     fs::{self, OpenOptions},
-    io::Write,
-    path::Path,
+    io::{self, Read, Write},
+    net::{Ipv4Addr, Ipv6Addr, SocketAddr, TcpStream, ToSocketAddrs, UdpSocket},
+    os::{
+        fd::{AsFd, AsRawFd, BorrowedFd, FromRawFd, OwnedFd},
+        unix::process::CommandExt,
+    },
+    process::{Command as ProcessCommand, Stdio},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex,
+    },
 };
 
 #[derive(Debug)]
@@ -14,146 +35,695 @@ pub struct CommandContext {
     pub command: Command,
     pub command_str: String,
     pub args: Vec<String>,
-    pub r_stderr: Option<(String, bool)>,
-    pub r_stdout: bool,
-    pub writer: RefCell<Writer>,
-    pub piped_stdin: Option<PipeReader>,
+    /// Prefix assignments that only apply to this command's environment.
+    pub env: Vec<(String, String)>,
+    pub io: RefCell<Io>,
 }
 
+/// What an open file descriptor refers to: one of the shell's own standard
+/// streams, an end of a pipe, a file, or a socket opened through a
+/// `/dev/tcp` or `/dev/udp` path.
 #[derive(Debug)]
-pub enum Writer {
-    Pipe(PipeWriter),
+pub enum Stream {
+    Stdin(io::Stdin),
+    Stdout(io::Stdout),
+    Stderr(io::Stderr),
+    PipeReader(PipeReader),
+    PipeWriter(PipeWriter),
     File(fs::File),
-    Stdout(std::io::Stdout),
+    Tcp(TcpStream),
+    Udp(UdpSocket),
 }
-impl Writer {
-    fn ref_mut(&mut self) -> &mut dyn Write {
+
+impl AsFd for Stream {
+    fn as_fd(&self) -> BorrowedFd<'_> {
         match self {
-            Writer::Pipe(p) => p,
-            Writer::File(f) => f,
-            Writer::Stdout(s) => s,
+            Stream::Stdin(s) => s.as_fd(),
+            Stream::Stdout(s) => s.as_fd(),
+            Stream::Stderr(s) => s.as_fd(),
+            Stream::PipeReader(p) => p.as_fd(),
+            Stream::PipeWriter(p) => p.as_fd(),
+            Stream::File(f) => f.as_fd(),
+            Stream::Tcp(s) => s.as_fd(),
+            Stream::Udp(s) => s.as_fd(),
         }
     }
 }
 
-pub fn parse_commands(input: &str) -> Result<Vec<CommandContext>> {
-    if input.is_empty() {
-        return Ok(vec![]);
+impl Read for Stream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            Stream::PipeReader(p) => p.read(buf),
+            Stream::File(f) => f.read(buf),
+            Stream::Tcp(s) => s.read(buf),
+            Stream::Udp(s) => s.recv(buf),
+            // Also bypasses the buffering of `Stdin`, which would read ahead
+            // of what the command consumes and keep it from the next one.
+            s => fs::File::from(s.as_fd().try_clone_to_owned()?).read(buf),
+        }
     }
-    let mut contexts = Vec::new();
-    for command in input.split('|') {
-        contexts.push(CommandContext::try_from(command.trim())?);
+}
+
+impl Write for Stream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Stream::Stdout(s) => s.write(buf),
+            Stream::Stderr(s) => s.write(buf),
+            Stream::PipeWriter(p) => p.write(buf),
+            Stream::File(f) => f.write(buf),
+            Stream::Tcp(s) => s.write(buf),
+            Stream::Udp(s) => s.send(buf),
+            s => fs::File::from(s.as_fd().try_clone_to_owned()?).write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Stream::Stdout(s) => s.flush(),
+            Stream::Stderr(s) => s.flush(),
+            _ => Ok(()),
+        }
     }
-    Ok(contexts)
 }
 
-impl TryFrom<&str> for CommandContext {
-    type Error = anyhow::Error;
-    fn try_from(input: &str) -> Result<Self> {
-        let mut args = parse_args(input);
-        let command_str = args.remove(0);
-        let command = Command::from(command_str.as_str());
+impl Stream {
+    pub fn try_clone(&self) -> Result<Self> {
+        Ok(match self {
+            Stream::Stdin(_) => Stream::Stdin(io::stdin()),
+            Stream::Stdout(_) => Stream::Stdout(io::stdout()),
+            Stream::Stderr(_) => Stream::Stderr(io::stderr()),
+            Stream::PipeReader(p) => Stream::PipeReader(p.try_clone()?),
+            Stream::PipeWriter(p) => Stream::PipeWriter(p.try_clone()?),
+            Stream::File(f) => Stream::File(f.try_clone()?),
+            Stream::Tcp(s) => Stream::Tcp(s.try_clone()?),
+            Stream::Udp(s) => Stream::Udp(s.try_clone()?),
+        })
+    }
+}
 
-        let (r_stdout, r_stderr, append) = args
-            .iter()
-            .find_map(|arg| match arg.as_str() {
-                ">" | "1>" => Some((true, false, false)),
-                ">>" | "1>>" => Some((true, false, true)),
-                "2>" => Some((false, true, false)),
-                "2>>" => Some((false, true, true)),
-                "&>" => Some((true, true, false)),
-                "&>>" => Some((true, true, true)),
-                _ => None,
-            })
-            .unwrap_or((false, false, false));
-
-        let file = if r_stdout || r_stderr {
-            let Some(file) = args.pop() else {
-                bail!("No file specified for redirection");
-            };
-            args.truncate(args.len() - 1);
-            let path = Path::new(&file);
-            if !path.exists() {
-                if let Some(parent) = path.parent() {
-                    fs::create_dir_all(parent)?;
-                    fs::File::create(&file)?;
+thread_local! {
+    /// Processes copying output to several files for `multios`, started while
+    /// redirecting the current command.
+    static TEES: RefCell<Vec<i32>> = const { RefCell::new(vec![]) };
+}
+
+/// Takes the process IDs of the `multios` copiers started since the last
+/// call. They finish once every copy of the descriptors they serve is closed.
+pub fn take_tees() -> Vec<i32> {
+    TEES.with(|tees| tees.take())
+}
+
+/// The shell's own descriptors, which commands start out with. Only `exec`
+/// changes them.
+/// Its `redirected` set holds every descriptor `exec` has changed.
+static SHELL_IO: Lazy<Mutex<Io>> = Lazy::new(|| Mutex::new(Io::default()));
+
+/// How many times `exec` has changed the shell's own descriptors.
+static SHELL_IO_CHANGES: AtomicUsize = AtomicUsize::new(0);
+
+/// Counts the changes `exec` has made to the shell's own descriptors, so that
+/// a running list can tell when to pick them up.
+pub fn shell_io_changes() -> usize {
+    SHELL_IO_CHANGES.load(Ordering::Relaxed)
+}
+
+/// The file descriptors a command sees, as inherited from its pipeline
+/// stage or enclosing compound command and changed by its redirections.
+/// Descriptors missing from the table are closed.
+#[derive(Debug)]
+pub struct Io {
+    fds: BTreeMap<u32, Stream>,
+    /// Whether standard output is the pipe to the next command of a
+    /// pipeline, which `multios` counts as one of the command's outputs.
+    piped_stdout: bool,
+    /// The descriptors changed by this command's own redirections.
+    redirected: BTreeSet<u32>,
+}
+
+impl Default for Io {
+    fn default() -> Self {
+        Self {
+            fds: BTreeMap::from([
+                (0, Stream::Stdin(io::stdin())),
+                (1, Stream::Stdout(io::stdout())),
+                (2, Stream::Stderr(io::stderr())),
+            ]),
+            piped_stdout: false,
+            redirected: BTreeSet::new(),
+        }
+    }
+}
+
+impl Io {
+    /// Duplicates the table for a command run within this one, which does
+    /// not count as the writing end of a pipeline.
+    pub fn try_clone(&self) -> Result<Self> {
+        let mut fds = BTreeMap::new();
+        for (fd, stream) in &self.fds {
+            fds.insert(*fd, stream.try_clone()?);
+        }
+        Ok(Self {
+            fds,
+            piped_stdout: false,
+            redirected: BTreeSet::new(),
+        })
+    }
+
+    /// A copy of the shell's own descriptors, as left by `exec`.
+    pub fn shell() -> Result<Self> {
+        SHELL_IO.lock().unwrap().try_clone()
+    }
+
+    /// A copy of this table with the descriptors `exec` has changed replaced
+    /// by the shell's own, for the commands after an `exec` in a list.
+    pub fn with_shell_changes(&self) -> Result<Self> {
+        let mut io = self.try_clone()?;
+        let shell = SHELL_IO.lock().unwrap();
+        for fd in &shell.redirected {
+            match shell.fds.get(fd) {
+                Some(stream) => io.set(*fd, stream.try_clone()?),
+                None => {
+                    io.fds.remove(fd);
                 }
             }
-            Some(file)
-        } else {
-            None
-        };
+        }
+        Ok(io)
+    }
 
-        Ok(Self {
-            command,
-            command_str,
-            args,
-            r_stderr: if r_stderr {
-                file.clone().map(|f| (f, append))
-            } else {
-                None
-            },
-            r_stdout,
-            piped_stdin: None,
-            writer: if r_stdout {
-                RefCell::new(Writer::File(
-                    OpenOptions::new()
-                        .write(true)
-                        .append(append)
-                        .truncate(!append)
-                        .open(file.unwrap())?,
-                ))
-            } else {
-                RefCell::new(Writer::Stdout(std::io::stdout()))
-            },
+    /// Closes every descriptor that neither this table nor the shell's own
+    /// uses, so that a forked subshell holds no other pipe open.
+    pub fn close_other_fds(&self) {
+        let shell = SHELL_IO.lock().unwrap();
+        let keep: Vec<i32> = self
+            .fds
+            .values()
+            .chain(shell.fds.values())
+            .map(|stream| stream.as_fd().as_raw_fd())
+            .collect();
+        close_other_fds(&keep);
+    }
+
+    /// Makes the descriptors changed by this table's redirections the
+    /// shell's own, for `exec` without a command.
+    pub fn keep_redirections(&self) -> Result<()> {
+        let mut shell = SHELL_IO.lock().unwrap();
+        for fd in &self.redirected {
+            match self.fds.get(fd) {
+                Some(stream) => shell.set(*fd, stream.try_clone()?),
+                None => {
+                    shell.fds.remove(fd);
+                }
+            }
+            shell.redirected.insert(*fd);
+        }
+        SHELL_IO_CHANGES.fetch_add(1, Ordering::Relaxed);
+        Ok(())
+    }
+
+    pub fn set(&mut self, fd: u32, stream: Stream) {
+        self.fds.insert(fd, stream);
+    }
+
+    /// Connects standard output to the next command of a pipeline.
+    pub fn set_piped_stdout(&mut self, writer: PipeWriter) {
+        self.set(1, Stream::PipeWriter(writer));
+        self.piped_stdout = true;
+    }
+
+    fn stream(&mut self, fd: u32) -> io::Result<&mut Stream> {
+        self.fds
+            .get_mut(&fd)
+            .ok_or_else(|| io::Error::from_raw_os_error(libc::EBADF))
+    }
+
+    pub fn write_fd(&mut self, fd: u32, msg: impl Display) -> Result<()> {
+        let stream = self.stream(fd).map_err(write_error)?;
+        // Written at once so that a datagram socket sends it as one message.
+        stream
+            .write_all(msg.to_string().as_bytes())
+            .map_err(write_error)?;
+        stream.flush().map_err(write_error)?;
+        Ok(())
+    }
+
+    pub fn ewriteln(&mut self, err: impl Display) -> Result<()> {
+        self.write_fd(2, format_args!("{}\n", err))
+    }
+
+    /// Applies redirections from left to right, so that `>out 2>&1` sends
+    /// both streams to `out` while `2>&1 >out` sends errors to the original
+    /// standard output.
+    ///
+    /// With `multios` set, a descriptor redirected for output more than once
+    /// writes to all of its targets, as does standard output redirected on
+    /// the writing end of a pipeline.
+    pub fn redirect(&mut self, redirects: &[Redirect]) -> Result<()> {
+        for redirect in redirects {
+            self.redirected.extend(redirected_fds(redirect));
+        }
+        if !option_enabled("multios") {
+            return redirects
+                .iter()
+                .try_for_each(|redirect| self.apply_redirect(redirect));
+        }
+        let mut outputs: BTreeMap<u32, Vec<Stream>> = BTreeMap::new();
+        if self.piped_stdout {
+            if let Some(pipe) = self.fds.get(&1) {
+                outputs.insert(1, vec![pipe.try_clone()?]);
+            }
+        }
+        for redirect in redirects {
+            self.apply_redirect(redirect)?;
+            for fd in output_fds(redirect) {
+                let Some(stream) = self.fds.get(&fd) else {
+                    outputs.remove(&fd);
+                    continue;
+                };
+                outputs.entry(fd).or_default().push(stream.try_clone()?);
+            }
+        }
+        for (fd, streams) in outputs {
+            if streams.len() > 1 {
+                self.set(fd, Stream::PipeWriter(tee(streams)?));
+            }
+        }
+        Ok(())
+    }
+
+    fn apply_redirect(&mut self, redirect: &Redirect) -> Result<()> {
+        let op = redirect.op;
+        let fd = redirect.fd.unwrap_or(default_fd(op));
+        match op {
+            Operator::Less | Operator::LessGreat => {
+                let file = expand_redirect_target(&redirect.target)?;
+                let stream = match open_socket(&file)? {
+                    Some(socket) => socket,
+                    None => Stream::File(open_input(&file, op == Operator::LessGreat)?),
+                };
+                self.set(fd, stream);
+            }
+            Operator::Great | Operator::Clobber | Operator::DGreat => {
+                let file = expand_redirect_target(&redirect.target)?;
+                let stream = match open_socket(&file)? {
+                    Some(socket) => socket,
+                    None => Stream::File(open_output(&file, op)?),
+                };
+                self.set(fd, stream);
+            }
+            Operator::AndGreat | Operator::AndDGreat => {
+                let file = expand_redirect_target(&redirect.target)?;
+                let stream = match open_socket(&file)? {
+                    Some(socket) => socket,
+                    None => Stream::File(open_output(&file, op)?),
+                };
+                self.set(2, stream.try_clone()?);
+                self.set(1, stream);
+            }
+            Operator::GreatAnd | Operator::LessAnd => {
+                let target = expand_single(&redirect.target)?;
+                match target.parse::<u32>() {
+                    _ if target == "-" => {
+                        self.fds.remove(&fd);
+                    }
+                    Ok(source) => {
+                        let stream = match self.fds.get(&source) {
+                            Some(stream) => stream.try_clone()?,
+                            None => bail!("{}: Bad file descriptor", source),
+                        };
+                        self.set(fd, stream);
+                    }
+                    // `>&file` is another way of writing `&>file`.
+                    Err(_) if op == Operator::GreatAnd && redirect.fd.is_none() => {
+                        self.apply_redirect(&Redirect {
+                            op: Operator::AndGreat,
+                            ..redirect.clone()
+                        })?;
+                    }
+                    Err(_) => bail!("{}: ambiguous redirect", redirect.target),
+                }
+            }
+            Operator::DLess | Operator::DLessDash => {
+                let doc = redirect.here_doc.clone().unwrap_or_default();
+                let body = match doc.expand {
+                    true => expand_here_doc(&doc.body)?,
+                    false => doc.body,
+                };
+                self.set(fd, Stream::PipeReader(text_reader(body)?));
+            }
+            Operator::TLess => {
+                let text = expand_single(&redirect.target)? + "\n";
+                self.set(fd, Stream::PipeReader(text_reader(text)?));
+            }
+            op => bail!("{}: redirection not supported", op),
+        }
+        Ok(())
+    }
+
+    fn stdio(&self, fd: u32) -> Result<Stdio> {
+        Ok(match self.fds.get(&fd) {
+            Some(stream) => Stdio::from(stream.as_fd().try_clone_to_owned()?),
+            None => Stdio::null(),
         })
     }
+
+    /// Passes the table on to a child process: the standard streams through
+    /// `Stdio`, and higher descriptors by duplicating them onto their numbers
+    /// right before the program is executed. Closed standard streams are
+    /// closed in the child too.
+    pub fn configure(&self, command: &mut ProcessCommand) -> Result<()> {
+        command
+            .stdin(self.stdio(0)?)
+            .stdout(self.stdio(1)?)
+            .stderr(self.stdio(2)?);
+        let closed: Vec<i32> = (0..3)
+            .filter(|fd| !self.fds.contains_key(fd))
+            .map(|fd| fd as i32)
+            .collect();
+        // Sources are first moved above every target so that no `dup2` can
+        // overwrite a source that is still needed.
+        let lowest = self.fds.keys().max().map_or(3, |max| max + 1).max(3) as i32;
+        let mut extra = vec![];
+        for (fd, stream) in self.fds.range(3..) {
+            extra.push((*fd as i32, dup_at_least(stream, lowest)?));
+        }
+        if closed.is_empty() && extra.is_empty() {
+            return Ok(());
+        }
+        // SAFETY: the closure only calls dup2 and close, which are
+        // async-signal-safe.
+        unsafe {
+            command.pre_exec(move || {
+                for (fd, source) in &extra {
+                    if libc::dup2(source.as_raw_fd(), *fd) == -1 {
+                        return Err(io::Error::last_os_error());
+                    }
+                }
+                for fd in &closed {
+                    libc::close(*fd);
+                }
+                Ok(())
+            });
+        }
+        Ok(())
+    }
+}
+
+/// The descriptor a redirection changes when none is given.
+fn default_fd(op: Operator) -> u32 {
+    match op {
+        Operator::Less
+        | Operator::LessGreat
+        | Operator::LessAnd
+        | Operator::DLess
+        | Operator::DLessDash
+        | Operator::TLess => 0,
+        _ => 1,
+    }
+}
+
+/// The descriptors that a redirection changes.
+fn redirected_fds(redirect: &Redirect) -> Vec<u32> {
+    match (redirect.op, redirect.fd) {
+        (Operator::AndGreat | Operator::AndDGreat, _) => vec![1, 2],
+        // `>&file` is another way of writing `&>file`.
+        (Operator::GreatAnd, None)
+            if redirect.target != "-" && redirect.target.parse::<u32>().is_err() =>
+        {
+            vec![1, 2]
+        }
+        (op, fd) => vec![fd.unwrap_or(default_fd(op))],
+    }
+}
+
+/// The descriptors that a redirection opens for writing.
+fn output_fds(redirect: &Redirect) -> Vec<u32> {
+    match redirect.op {
+        Operator::GreatAnd if redirect.target == "-" => vec![],
+        Operator::Great
+        | Operator::Clobber
+        | Operator::DGreat
+        | Operator::GreatAnd
+        | Operator::AndGreat
+        | Operator::AndDGreat => redirected_fds(redirect),
+        _ => vec![],
+    }
+}
+
+/// Returns a pipe whose input a forked copy of the shell copies to every one
+/// of `outputs`, for `multios`. Copying stops at the first failed write, such
+/// as to a pipe whose reader has exited, which then closes the returned
+/// pipe's reading end too.
+fn tee(mut outputs: Vec<Stream>) -> Result<PipeWriter> {
+    let (mut reader, writer) = os_pipe::pipe()?;
+    let Some(pid) = fork_shell()? else {
+        let mut keep: Vec<i32> = outputs
+            .iter()
+            .map(|output| output.as_fd().as_raw_fd())
+            .collect();
+        keep.push(reader.as_raw_fd());
+        close_other_fds(&keep);
+        let mut buf = [0; 8192];
+        while let Ok(len @ 1..) = reader.read(&mut buf) {
+            let copied = outputs
+                .iter_mut()
+                .try_for_each(|output| output.write_all(&buf[..len]).and_then(|()| output.flush()));
+            if copied.is_err() {
+                break;
+            }
+        }
+        // SAFETY: exits without running the parent's exit handlers.
+        unsafe { libc::_exit(0) }
+    };
+    TEES.with(|tees| tees.borrow_mut().push(pid));
+    Ok(writer)
+}
+
+/// Closes every descriptor above the standard three that is not in `keep`,
+/// so that a forked helper holds no other pipe open.
+fn close_other_fds(keep: &[i32]) {
+    let fds: Vec<i32> = fs::read_dir("/dev/fd")
+        .into_iter()
+        .flatten()
+        .flatten()
+        .filter_map(|entry| entry.file_name().to_str()?.parse().ok())
+        .filter(|fd| *fd > 2 && !keep.contains(fd))
+        .collect();
+    for fd in fds {
+        // SAFETY: the helper exits without using the descriptors it closes.
+        unsafe { libc::close(fd) };
+    }
+}
+
+/// Duplicates a stream's descriptor onto the lowest free number that is at
+/// least `lowest`.
+fn dup_at_least(stream: &Stream, lowest: i32) -> Result<OwnedFd> {
+    // SAFETY: fcntl is given a valid descriptor, and the one it returns is
+    // owned by nothing else.
+    unsafe {
+        match libc::fcntl(stream.as_fd().as_raw_fd(), libc::F_DUPFD_CLOEXEC, lowest) {
+            -1 => Err(io::Error::last_os_error().into()),
+            fd => Ok(OwnedFd::from_raw_fd(fd)),
+        }
+    }
+}
+
+fn write_error(e: io::Error) -> anyhow::Error {
+    anyhow!("write error: {}", describe(&e))
+}
+
+/// Opens a file for `>`, `>>`, `>|`, `&>` or `&>>`. With `noclobber` set,
+/// only `>|` may truncate an existing regular file.
+fn open_output(file: &str, op: Operator) -> Result<fs::File> {
+    let append = matches!(op, Operator::DGreat | Operator::AndDGreat);
+    if !append
+        && op != Operator::Clobber
+        && option_enabled("noclobber")
+        && fs::metadata(file).is_ok_and(|meta| meta.is_file())
+    {
+        bail!("{}: cannot overwrite existing file", file);
+    }
+    OpenOptions::new()
+        .create(true)
+        .write(true)
+        .append(append)
+        .truncate(!append)
+        .open(file)
+        .map_err(|e| anyhow!("{}: {}", file, describe(&e)))
+}
+
+/// Opens a file for `<`, or for reading and writing with `<>`, which creates
+/// it if needed.
+fn open_input(file: &str, write: bool) -> Result<fs::File> {
+    OpenOptions::new()
+        .read(true)
+        .write(write)
+        .create(write)
+        .open(file)
+        .map_err(|e| anyhow!("{}: {}", file, describe(&e)))
+}
+
+/// Returns a pipe that yields the text of a here-document or here-string.
+/// A forked copy of the shell writes the text so that texts larger than the
+/// pipe's buffer cannot block the shell. That copy forks the writer and exits
+/// at once, so nothing is left for the shell to wait for.
+fn text_reader(text: String) -> Result<PipeReader> {
+    let (reader, mut writer) = os_pipe::pipe()?;
+    let Some(pid) = fork_shell()? else {
+        drop(reader);
+        close_other_fds(&[writer.as_raw_fd()]);
+        if let Ok(None) = fork_shell() {
+            let _ = writer.write_all(text.as_bytes());
+        }
+        // SAFETY: exits without running the parent's exit handlers.
+        unsafe { libc::_exit(0) }
+    };
+    drop(writer);
+    wait_forked(pid);
+    Ok(reader)
+}
+
+/// Connects a socket for a `/dev/tcp/host/port` or `/dev/udp/host/port`
+/// path. Returns `None` for any other path.
+fn open_socket(path: &str) -> Result<Option<Stream>> {
+    let (udp, address) = match (
+        path.strip_prefix("/dev/tcp/"),
+        path.strip_prefix("/dev/udp/"),
+    ) {
+        (Some(address), _) => (false, address),
+        (_, Some(address)) => (true, address),
+        _ => return Ok(None),
+    };
+    let Some((host, port)) = address.split_once('/') else {
+        return Ok(None);
+    };
+    let port: u16 = port
+        .parse()
+        .map_err(|_| anyhow!("{}: invalid port", path))?;
+    let stream = if udp {
+        connect_udp(host, port).map(Stream::Udp)
+    } else {
+        TcpStream::connect((host, port)).map(Stream::Tcp)
+    };
+    stream
+        .map(Some)
+        .map_err(|e| anyhow!("{}: {}", path, describe(&e)))
+}
+
+/// Connects a UDP socket to the first address of `host` it can reach.
+fn connect_udp(host: &str, port: u16) -> io::Result<UdpSocket> {
+    let mut last_error = io::Error::from(io::ErrorKind::NotFound);
+    for addr in (host, port).to_socket_addrs()? {
+        let local: SocketAddr = match addr {
+            SocketAddr::V4(_) => (Ipv4Addr::UNSPECIFIED, 0).into(),
+            SocketAddr::V6(_) => (Ipv6Addr::UNSPECIFIED, 0).into(),
+        };
+        let socket = UdpSocket::bind(local)?;
+        match socket.connect(addr) {
+            Ok(()) => return Ok(socket),
+            Err(e) => last_error = e,
+        }
+    }
+    Err(last_error)
+}
+
+/// Describes an I/O error without the error code that `Display` appends,
+/// e.g. "No such file or directory".
+fn describe(e: &io::Error) -> String {
+    let msg = e.to_string();
+    match msg.find(" (os error ") {
+        Some(end) => msg[..end].to_string(),
+        None => msg,
+    }
 }
 
 impl CommandContext {
+    /// Creates a context that has no command yet and uses the given streams.
+    pub fn new(io: Io) -> Self {
+        Self {
+            command: Command::Invalid,
+            command_str: String::new(),
+            args: vec![],
+            env: vec![],
+            io: RefCell::new(io),
+        }
+    }
+
+    /// Expands a simple command into this context and applies its
+    /// redirections. Returns `false` when no command name is left after
+    /// expansion, in which case assignments set shell variables instead of
+    /// going into the command's environment, unless the command is one stage
+    /// of a longer pipeline.
+    pub fn expand(&mut self, cmd: &SimpleCommand, in_pipeline: bool) -> Result<bool> {
+        let mut args = vec![];
+        for word in &cmd.words {
+            args.extend(expand_word(word)?);
+        }
+        let mut env = vec![];
+        for assignment in &cmd.assignments {
+            let value = expand_assignment(&assignment.value)?;
+            if args.is_empty() {
+                if !in_pipeline {
+                    set_var(&assignment.name, &value)?;
+                }
+            } else {
+                env.push((assignment.name.clone(), value));
+            }
+        }
+        self.io.get_mut().redirect(&cmd.redirects)?;
+        if args.is_empty() {
+            return Ok(false);
+        }
+        self.command_str = args.remove(0);
+        self.command = Command::from(self.command_str.as_str());
+        self.args = args;
+        self.env = env;
+        Ok(true)
+    }
+
+    /// Reads one line of input without its newline, or `None` at end of
+    /// input. Reads a byte at a time so that nothing after the line is taken
+    /// from a stream shared with later commands.
+    pub fn read_line(&self) -> Result<Option<String>> {
+        let mut io = self.io.borrow_mut();
+        let reader = io.stream(0)?;
+        let mut line = vec![];
+        let mut byte = [0];
+        loop {
+            match reader.read(&mut byte)? {
+                0 if line.is_empty() => return Ok(None),
+                0 => break,
+                _ if byte[0] == b'\n' => break,
+                _ => line.push(byte[0]),
+            }
+        }
+        Ok(Some(String::from_utf8_lossy(&line).into_owned()))
+    }
+
     pub fn writeln(&self, msg: impl Display) -> Result<()> {
-        let mut writer = self.writer.borrow_mut();
-        writeln!(writer.ref_mut(), "{}", msg)?;
-        writer.ref_mut().flush()?;
-        Ok(())
+        self.write(format_args!("{}\n", msg))
     }
 
     pub fn write(&self, msg: impl Display) -> Result<()> {
-        let mut writer = self.writer.borrow_mut();
-        write!(writer.ref_mut(), "{}", msg)?;
-        writer.ref_mut().flush()?;
-        Ok(())
+        self.io.borrow_mut().write_fd(1, msg)
     }
 
     pub fn ewriteln(&self, err: impl Display) -> Result<()> {
-        self.ewrite(format!("{}\n", err))
+        self.ewrite(format_args!("{}\n", err))
     }
 
     pub fn ewrite(&self, err: impl Display) -> Result<()> {
-        if let Some((file, append)) = &self.r_stderr {
-            if self.r_stdout {
-                let mut writer = self.writer.borrow_mut();
-                write!(writer.ref_mut(), "{}", err)?;
-                writer.ref_mut().flush()?;
-            } else if let Some(mut writer) = create_file_writer(file, *append) {
-                write!(writer, "{}", err)?;
-                writer.flush()?
-            }
-        } else {
-            eprint!("{}", err);
-        }
-        Ok(())
+        self.io.borrow_mut().write_fd(2, err)
     }
 }
 
-pub fn create_file_writer(file: &str, append: bool) -> Option<fs::File> {
-    let parent = std::path::Path::new(&file).parent()?;
-    fs::create_dir_all(parent).ok()?;
+/// Opens a file for writing, such as the history file. Missing parent
+/// directories are an error rather than being created.
+pub fn create_file_writer(file: &str, append: bool) -> Result<fs::File> {
     OpenOptions::new()
         .create(true)
         .write(true)
         .append(append)
         .truncate(!append)
         .open(file)
-        .ok()
+        .map_err(|e| anyhow!("{}: {}", file, describe(&e)))
 }
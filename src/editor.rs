@@ -1,5 +1,5 @@
 use anyhow::Result;
-use std::{env, fs};
+use std::fs;
 
 use once_cell::unsync::OnceCell;
 use rustyline::{
@@ -11,10 +11,13 @@ use rustyline::{
 };
 use trie_rs::Trie;
 
-use crate::{command::is_executable, history::CommandHistory};
+use crate::{command::is_executable, history::CommandHistory, vars::get_var};
 
 pub type ShellEditor = Editor<ShellCompleter, FileHistory>;
-const BUILTIN_COMMANDS: &[&str] = &["exit", "echo", "type", "pwd", "cd"];
+const BUILTIN_COMMANDS: &[&str] = &[
+    "exit", "echo", "type", "pwd", "cd", "export", "unset", "readonly", "let", "shopt", "read",
+    "set", "exec", "jobs", "wait", "return", "break", "continue",
+];
 
 pub fn get_editor() -> ShellEditor {
     let mut editor = Editor::new().unwrap();
@@ -94,7 +97,7 @@ fn build_completion_trie() -> Trie<u8> {
 }
 
 fn get_executables() -> Vec<String> {
-    env::var("PATH")
+    get_var("PATH")
         .unwrap_or_default()
         .split(':')
         .filter_map(|path| fs::read_dir(path).ok())
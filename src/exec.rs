@@ -0,0 +1,627 @@
+use anyhow::{bail, Context, Result};
+use std::{
+    cell::{Cell, RefCell},
+    fs::{self, File},
+    io::{self, Read, Write},
+    os::{
+        fd::{AsFd, AsRawFd},
+        unix::process::ExitStatusExt,
+    },
+    process::{Child, Command as ProcessCommand},
+};
+
+use os_pipe::PipeReader;
+
+use crate::{
+    arith::evaluate,
+    command::{handle_command, Command, ExitRequest, LoopControl, ReturnRequest},
+    context::{shell_io_changes, take_tees, CommandContext, Io, Stream},
+    expand::{expand_pattern, expand_single, expand_word},
+    functions::{define_function, get_function, track_call},
+    jobs::add_job,
+    parser::{
+        parse, AndOrList, CaseItem, CommandNode, CompoundCommand, Connector, List, Pipeline,
+        Redirect,
+    },
+    vars::{
+        exported_env, positional_params, restore_var, save_var, set_last_status,
+        set_positional_params, set_var,
+    },
+};
+
+thread_local! {
+    /// Status of the last command substitution, which becomes the status of a
+    /// command that has no command name, as in `x=$(false)`.
+    static SUBSTITUTION_STATUS: Cell<Option<i32>> = const { Cell::new(None) };
+
+    /// Process substitutions started while expanding the current command.
+    static PROCESS_SUBSTITUTIONS: RefCell<Vec<ProcessSubstitution>> =
+        const { RefCell::new(vec![]) };
+
+    /// How many loops are running, one inside another, for `break` and
+    /// `continue`.
+    static LOOP_DEPTH: Cell<usize> = const { Cell::new(0) };
+}
+
+/// A command run for `<(...)` or `>(...)`. The shell's end of its pipe stays
+/// open under its own descriptor number until the command using the
+/// `/dev/fd` path has started.
+struct ProcessSubstitution {
+    fd: u32,
+    stream: Stream,
+    pid: i32,
+}
+
+fn take_process_substitutions() -> Vec<ProcessSubstitution> {
+    PROCESS_SUBSTITUTIONS.with(|subs| subs.take())
+}
+
+pub fn execute_commands(list: &List, io: &Io) -> Result<i32> {
+    let mut status = 0;
+    let mut io = ListIo::new(io);
+    for and_or in &list.0 {
+        status = if and_or.background {
+            run_in_background(and_or, io.get())?;
+            set_last_status(0);
+            0
+        } else {
+            execute_and_or(and_or, io.get())?
+        };
+        io.refresh()?;
+    }
+    Ok(status)
+}
+
+/// The descriptors the commands of a list start out with, which an `exec`
+/// in one of them changes for those after it.
+struct ListIo<'a> {
+    io: &'a Io,
+    changed: Option<Io>,
+    changes: usize,
+}
+
+impl<'a> ListIo<'a> {
+    fn new(io: &'a Io) -> Self {
+        Self {
+            io,
+            changed: None,
+            changes: shell_io_changes(),
+        }
+    }
+
+    fn get(&self) -> &Io {
+        self.changed.as_ref().unwrap_or(self.io)
+    }
+
+    /// Picks up the shell's descriptors if `exec` changed them since the
+    /// last call.
+    fn refresh(&mut self) -> Result<()> {
+        let changes = shell_io_changes();
+        if changes != self.changes {
+            self.changed = Some(self.io.with_shell_changes()?);
+            self.changes = changes;
+        }
+        Ok(())
+    }
+}
+
+/// Runs a list followed by `&` in a forked copy of the shell and announces
+/// it as a job, e.g. `[1] 12345`. Its standard input is `/dev/null` unless
+/// redirected.
+fn run_in_background(and_or: &AndOrList, io: &Io) -> Result<()> {
+    let mut io = io.try_clone()?;
+    io.set(0, Stream::File(File::open("/dev/null")?));
+    match fork_shell()? {
+        None => {
+            let status = subshell_status(execute_and_or(and_or, &io), &mut io);
+            exit_forked(status)
+        }
+        Some(pid) => {
+            let id = add_job(pid, and_or.to_string());
+            io.ewriteln(format_args!("[{}] {}", id, pid))
+        }
+    }
+}
+
+/// Forks a copy of the shell, returning the child's process ID in the
+/// parent and `None` in the child. The child must leave through `_exit`,
+/// normally by way of `exit_forked`.
+///
+/// The shell never starts threads: anything that runs alongside it, such as
+/// a pipeline stage or a `multios` copier, is a forked process instead. This
+/// refuses to fork if another thread exists all the same.
+pub(crate) fn fork_shell() -> Result<Option<i32>> {
+    if thread_count() > 1 {
+        bail!("fork: other threads are running");
+    }
+    // Anything still buffered would otherwise be written by both processes.
+    io::stdout().flush()?;
+    // SAFETY: this is the only thread, so the child gets a copy of every
+    // lock and allocator state as this thread left it, not one another
+    // thread was half-way through changing. The child leaves through `_exit`,
+    // so nothing of the parent is torn down twice.
+    match unsafe { libc::fork() } {
+        -1 => Err(io::Error::last_os_error().into()),
+        0 => {
+            // The parent's pending substitutions and copiers are its own to
+            // wait for.
+            drop(take_process_substitutions());
+            drop(take_tees());
+            Ok(None)
+        }
+        pid => Ok(Some(pid)),
+    }
+}
+
+/// Number of threads in the shell's process, or 1 where `/proc` can't tell.
+fn thread_count() -> usize {
+    fs::read_dir("/proc/self/task").map_or(1, |tasks| tasks.count())
+}
+
+/// The status a subshell ends with, after reporting any error that stopped
+/// its commands.
+fn subshell_status(result: Result<i32>, io: &mut Io) -> i32 {
+    catch_exit(result).unwrap_or_else(|e| {
+        let _ = io.ewriteln(e);
+        1
+    })
+}
+
+fn exit_forked(status: i32) -> ! {
+    let _ = io::stdout().flush();
+    // SAFETY: exits without running the parent's exit handlers.
+    unsafe { libc::_exit(status) }
+}
+
+/// Waits for a forked child and returns its status the way `$?` reports it.
+pub(crate) fn wait_forked(pid: i32) -> i32 {
+    let mut status = 0;
+    // SAFETY: waitpid only writes to the status it is given.
+    while unsafe { libc::waitpid(pid, &mut status, 0) } == -1 {
+        if io::Error::last_os_error().kind() != io::ErrorKind::Interrupted {
+            return 1;
+        }
+    }
+    if libc::WIFSIGNALED(status) {
+        128 + libc::WTERMSIG(status)
+    } else {
+        libc::WEXITSTATUS(status)
+    }
+}
+
+fn execute_and_or(and_or: &AndOrList, io: &Io) -> Result<i32> {
+    let mut io = ListIo::new(io);
+    let mut status = execute_pipeline(&and_or.first, io.get())?;
+    set_last_status(status);
+    io.refresh()?;
+    for (connector, pipeline) in &and_or.rest {
+        let should_run = match connector {
+            Connector::And => status == 0,
+            Connector::Or => status != 0,
+        };
+        if should_run {
+            status = execute_pipeline(pipeline, io.get())?;
+            set_last_status(status);
+            io.refresh()?;
+        }
+    }
+    Ok(status)
+}
+
+enum Stage {
+    Process(Child),
+    Forked(i32),
+    Finished(i32),
+}
+
+impl Stage {
+    fn wait(self) -> i32 {
+        match self {
+            Stage::Process(mut child) => match child.wait() {
+                Ok(status) => status
+                    .code()
+                    .or_else(|| status.signal().map(|signal| 128 + signal))
+                    .unwrap_or(1),
+                Err(_) => 1,
+            },
+            Stage::Forked(pid) => wait_forked(pid),
+            Stage::Finished(status) => status,
+        }
+    }
+}
+
+fn execute_pipeline(pipeline: &Pipeline, io: &Io) -> Result<i32> {
+    let mut prev_reader = None;
+    let mut stages = vec![];
+    let mut substitutions = vec![];
+    let mut tees = vec![];
+    let last_idx = pipeline.commands.len() - 1;
+    for (idx, node) in pipeline.commands.iter().enumerate() {
+        let mut stage_io = io.try_clone()?;
+        if let Some(prev) = prev_reader.take() {
+            stage_io.set(0, Stream::PipeReader(prev));
+        }
+        if idx != last_idx {
+            let (reader, writer) = os_pipe::pipe()?;
+            stage_io.set_piped_stdout(writer);
+            prev_reader = Some(reader);
+        }
+        let (compound, redirects) = match node {
+            CommandNode::Simple(cmd) => {
+                let mut ctx = CommandContext::new(stage_io);
+                SUBSTITUTION_STATUS.with(|cell| cell.take());
+                let expanded = ctx.expand(cmd, last_idx != 0);
+                for sub in take_process_substitutions() {
+                    ctx.io.get_mut().set(sub.fd, sub.stream);
+                    substitutions.push(sub.pid);
+                }
+                // Output that `exec` makes permanent is copied for as long as
+                // the shell runs.
+                let new_tees = take_tees();
+                if !matches!(ctx.command, Command::Exec) {
+                    tees.extend(new_tees);
+                }
+                match expanded {
+                    Ok(true) => {}
+                    Ok(false) => {
+                        let status = SUBSTITUTION_STATUS.with(|cell| cell.take());
+                        stages.push(Stage::Finished(status.unwrap_or(0)));
+                        continue;
+                    }
+                    Err(e) => {
+                        let _ = ctx.ewriteln(e);
+                        stages.push(Stage::Finished(1));
+                        continue;
+                    }
+                }
+                let stage = match &ctx.command {
+                    Command::Executable { .. } => match run_executable(&mut ctx) {
+                        Ok(child) => Stage::Process(child),
+                        Err(e) => {
+                            let _ = ctx.ewriteln(e);
+                            Stage::Finished(126)
+                        }
+                    },
+                    _ if last_idx == 0 => Stage::Finished(run_builtin(&mut ctx)?),
+                    _ => fork_stage(&mut prev_reader, || run_builtin(&mut ctx))?,
+                };
+                stages.push(stage);
+                continue;
+            }
+            CommandNode::Compound(compound, redirects) => (compound, redirects),
+            // A definition in a longer pipeline only reaches its subshell.
+            CommandNode::Function(def) => {
+                if last_idx == 0 {
+                    define_function(def.clone());
+                }
+                stages.push(Stage::Finished(0));
+                continue;
+            }
+        };
+        let stage = if last_idx == 0 {
+            Stage::Finished(run_compound(compound, redirects, stage_io)?)
+        } else {
+            fork_stage(&mut prev_reader, || {
+                run_compound(compound, redirects, stage_io)
+            })?
+        };
+        stages.push(stage);
+    }
+
+    let statuses: Vec<i32> = stages.into_iter().map(Stage::wait).collect();
+    for pid in substitutions {
+        wait_forked(pid);
+    }
+    for pid in tees {
+        wait_forked(pid);
+    }
+    let status = statuses.last().copied().unwrap_or(0);
+    Ok(match pipeline.negated {
+        true => i32::from(status == 0),
+        false => status,
+    })
+}
+
+/// Runs a builtin or compound command that is one stage of a longer pipeline
+/// in a forked subshell. `next` is the reading end of the pipe to the
+/// following stage, which only the shell keeps open.
+fn fork_stage(next: &mut Option<PipeReader>, run: impl FnOnce() -> Result<i32>) -> Result<Stage> {
+    match fork_shell()? {
+        None => {
+            drop(next.take());
+            exit_forked(catch_exit(run()).unwrap_or(1))
+        }
+        Some(pid) => Ok(Stage::Forked(pid)),
+    }
+}
+
+fn run_compound(compound: &CompoundCommand, redirects: &[Redirect], mut io: Io) -> Result<i32> {
+    let redirected = io.redirect(redirects);
+    // The redirections have opened any `/dev/fd` paths by now.
+    let substitutions = take_process_substitutions();
+    let tees = take_tees();
+    let status = match redirected {
+        Ok(()) => run_redirected_compound(compound, io),
+        Err(e) => {
+            let _ = io.ewriteln(e);
+            drop(io);
+            Ok(1)
+        }
+    };
+    for sub in substitutions {
+        drop(sub.stream);
+        wait_forked(sub.pid);
+    }
+    for pid in tees {
+        wait_forked(pid);
+    }
+    status
+}
+
+fn run_redirected_compound(compound: &CompoundCommand, mut io: Io) -> Result<i32> {
+    let result = match compound {
+        CompoundCommand::BraceGroup(list) => execute_commands(list, &io),
+        CompoundCommand::Subshell(list) => run_subshell(list, &mut io),
+        CompoundCommand::Arith(expr) => expand_single(expr)
+            .and_then(|expr| evaluate(&expr))
+            .map(|value| i32::from(value == 0)),
+        CompoundCommand::If {
+            branches,
+            otherwise,
+        } => run_if(branches, otherwise.as_ref(), &io),
+        CompoundCommand::Loop {
+            condition,
+            body,
+            until,
+        } => run_loop(
+            || Ok((execute_commands(condition, &io)? == 0) != *until),
+            body,
+            &io,
+        ),
+        CompoundCommand::For { name, words, body } => run_for(name, words.as_deref(), body, &io),
+        CompoundCommand::Case { word, items } => run_case(word, items, &io),
+    };
+    match result {
+        Err(e) if !unwinds(&e) => {
+            let _ = io.ewriteln(e);
+            Ok(1)
+        }
+        result => result,
+    }
+}
+
+/// Runs the body of the first branch whose condition succeeds, or else the
+/// `else` body. Without either the status is 0.
+fn run_if(branches: &[(List, List)], otherwise: Option<&List>, io: &Io) -> Result<i32> {
+    for (condition, body) in branches {
+        if execute_commands(condition, io)? == 0 {
+            return execute_commands(body, io);
+        }
+    }
+    match otherwise {
+        Some(body) => execute_commands(body, io),
+        None => Ok(0),
+    }
+}
+
+/// Runs the body for as long as `proceed` allows, handling `break` and
+/// `continue`. The status is that of the last pass of the body, or 0.
+fn run_loop(mut proceed: impl FnMut() -> Result<bool>, body: &List, io: &Io) -> Result<i32> {
+    LOOP_DEPTH.with(|depth| depth.set(depth.get() + 1));
+    let mut run = || {
+        let mut status = 0;
+        while proceed()? {
+            status = match execute_commands(body, io) {
+                Err(e) => match e.downcast_ref::<LoopControl>() {
+                    Some(LoopControl::Break(1)) => return Ok(0),
+                    Some(LoopControl::Break(n)) => return Err(LoopControl::Break(n - 1).into()),
+                    Some(LoopControl::Continue(1)) => 0,
+                    Some(LoopControl::Continue(n)) => {
+                        return Err(LoopControl::Continue(n - 1).into())
+                    }
+                    None => return Err(e),
+                },
+                result => result?,
+            };
+        }
+        Ok(status)
+    };
+    let result = run();
+    LOOP_DEPTH.with(|depth| depth.set(depth.get() - 1));
+    result
+}
+
+pub(crate) fn loop_depth() -> usize {
+    LOOP_DEPTH.with(Cell::get)
+}
+
+/// Runs the body with the variable set to each of the expanded words, or to
+/// each positional parameter.
+fn run_for(name: &str, words: Option<&[String]>, body: &List, io: &Io) -> Result<i32> {
+    let values = match words {
+        Some(words) => {
+            let mut values = vec![];
+            for word in words {
+                values.extend(expand_word(word)?);
+            }
+            values
+        }
+        None => positional_params(),
+    };
+    let mut values = values.into_iter();
+    run_loop(
+        || match values.next() {
+            Some(value) => set_var(name, &value).map(|()| true),
+            None => Ok(false),
+        },
+        body,
+        io,
+    )
+}
+
+/// Runs the body of the first item with a pattern matching the word. Without
+/// a match the status is 0.
+fn run_case(word: &str, items: &[CaseItem], io: &Io) -> Result<i32> {
+    let word = expand_single(word)?;
+    for item in items {
+        for pattern in &item.patterns {
+            if expand_pattern(pattern)?.matches(&word) {
+                return execute_commands(&item.body, io);
+            }
+        }
+    }
+    Ok(0)
+}
+
+/// Calls a function with the command's arguments as its positional
+/// parameters, which are put back afterwards. `return` ends it early.
+pub(crate) fn call_function(name: &str, ctx: &mut CommandContext) -> Result<i32> {
+    let Some(def) = get_function(name) else {
+        bail!("{}: command not found", name);
+    };
+    let io = std::mem::take(ctx.io.get_mut());
+    let saved = set_positional_params(std::mem::take(&mut ctx.args));
+    let result = track_call(|| run_compound(&def.body, &def.redirects, io));
+    set_positional_params(saved);
+    match result {
+        Err(e) => match e.downcast_ref::<ReturnRequest>() {
+            Some(ReturnRequest(code)) => Ok(*code),
+            None => Err(e),
+        },
+        result => result,
+    }
+}
+
+/// Runs `( ... )` in a forked copy of the shell, so that nothing it changes,
+/// whether variables, options, the directory or the shell's own descriptors,
+/// reaches the shell itself, and `exec` replaces only the copy.
+fn run_subshell(list: &List, io: &mut Io) -> Result<i32> {
+    match fork_shell()? {
+        None => exit_forked(subshell_status(execute_commands(list, io), io)),
+        Some(pid) => Ok(wait_forked(pid)),
+    }
+}
+
+/// Runs a builtin, reporting its errors. Only a request to exit is passed on.
+/// Prefix assignments are set as shell variables while it runs and, unless
+/// it is a special builtin, put back as they were afterwards.
+fn run_builtin(ctx: &mut CommandContext) -> Result<i32> {
+    let saved: Vec<_> = if ctx.command.is_special() {
+        vec![]
+    } else {
+        ctx.env
+            .iter()
+            .map(|(name, _)| (name.clone(), save_var(name)))
+            .collect()
+    };
+    let assigned = ctx
+        .env
+        .iter()
+        .try_for_each(|(name, value)| set_var(name, value));
+    let result = assigned.and_then(|()| handle_command(ctx));
+    for (name, var) in saved.into_iter().rev() {
+        restore_var(&name, var);
+    }
+    match result {
+        Err(e) if !unwinds(&e) => {
+            let _ = ctx.ewriteln(e);
+            Ok(1)
+        }
+        result => result,
+    }
+}
+
+/// Whether an error is a request from `exit`, `return`, `break` or
+/// `continue`, which is passed on to what it ends rather than reported.
+fn unwinds(e: &anyhow::Error) -> bool {
+    e.is::<ExitRequest>() || e.is::<ReturnRequest>() || e.is::<LoopControl>()
+}
+
+/// Turns a request to exit into the status it asks for, for lists that run
+/// apart from the shell's top level. A `return`, `break` or `continue` ends
+/// such a list too, as it cannot reach past it.
+fn catch_exit(result: Result<i32>) -> Result<i32> {
+    match result {
+        Err(e) => {
+            if let Some(ExitRequest(code)) = e.downcast_ref() {
+                Ok(*code)
+            } else if let Some(ReturnRequest(code)) = e.downcast_ref() {
+                Ok(*code)
+            } else if e.is::<LoopControl>() {
+                Ok(0)
+            } else {
+                Err(e)
+            }
+        }
+        result => result,
+    }
+}
+
+fn run_executable(ctx: &mut CommandContext) -> Result<Child> {
+    let Command::Executable { name, .. } = &ctx.command else {
+        unreachable!("run_executable called with non-executable command");
+    };
+    let mut command = ProcessCommand::new(name);
+    command
+        .args(&ctx.args)
+        .env_clear()
+        .envs(exported_env())
+        .envs(ctx.env.iter().cloned());
+    ctx.io.borrow().configure(&mut command)?;
+    command.spawn().context("Failed to execute command")
+}
+
+/// Starts `source` in a subshell with its output on a pipe for `<(...)`, or
+/// with its input on one for `>(...)`, and returns a `/dev/fd` path for the
+/// other end.
+pub fn substitute_process(source: &str, read: bool) -> Result<String> {
+    let list = parse(source)?;
+    let (reader, writer) = os_pipe::pipe()?;
+    let mut io = Io::shell()?;
+    let stream = if read {
+        io.set(1, Stream::PipeWriter(writer));
+        Stream::PipeReader(reader)
+    } else {
+        io.set(0, Stream::PipeReader(reader));
+        Stream::PipeWriter(writer)
+    };
+    let fd = stream.as_fd().as_raw_fd() as u32;
+    let Some(pid) = fork_shell()? else {
+        drop(stream);
+        io.close_other_fds();
+        let status = catch_exit(execute_commands(&list, &io)).unwrap_or_else(|e| {
+            let _ = io.ewriteln(e);
+            1
+        });
+        drop(io);
+        exit_forked(status)
+    };
+    drop(io);
+    PROCESS_SUBSTITUTIONS.with(|subs| {
+        subs.borrow_mut()
+            .push(ProcessSubstitution { fd, stream, pid })
+    });
+    Ok(format!("/dev/fd/{}", fd))
+}
+
+/// Runs `source` in a forked subshell and returns what it wrote to stdout,
+/// minus trailing newlines, for `$(...)` and backquote substitution.
+pub fn capture_output(source: &str) -> Result<String> {
+    let list = parse(source)?;
+    let (mut reader, writer) = os_pipe::pipe()?;
+    let mut io = Io::shell()?;
+    io.set(1, Stream::PipeWriter(writer));
+    let Some(pid) = fork_shell()? else {
+        drop(reader);
+        exit_forked(subshell_status(execute_commands(&list, &io), &mut io))
+    };
+    drop(io);
+    let mut output = vec![];
+    let read = reader.read_to_end(&mut output);
+    let status = wait_forked(pid);
+    read?;
+    set_last_status(status);
+    SUBSTITUTION_STATUS.with(|cell| cell.set(Some(status)));
+    let output = String::from_utf8_lossy(&output);
+    Ok(output.trim_end_matches('\n').to_string())
+}
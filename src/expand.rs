@@ -0,0 +1,621 @@
+use anyhow::{anyhow, bail, Result};
+use std::ffi::{CStr, CString};
+
+use crate::{
+    arith::evaluate,
+    brace::expand_braces,
+    exec::{capture_output, substitute_process},
+    glob::glob,
+    jobs::last_background_pid,
+    lexer::{BS, DQ, SQ},
+    options::shopt_enabled,
+    pattern::Pattern,
+    vars::{
+        get_var, is_name_char, is_name_start, is_valid_name, last_status, positional_params,
+        set_var,
+    },
+};
+
+/// Expanded characters, each flagged with whether it came from a quoted
+/// context. Only unquoted characters take part in field splitting.
+type Expanded = Vec<(char, bool)>;
+
+/// Expands a word as produced by the lexer into the fields it stands for:
+/// brace expansion, parameter expansion, process substitution, splitting of
+/// unquoted results on whitespace, pathname expansion and quote removal.
+/// Nothing inside single quotes is expanded.
+pub fn expand_word(word: &str) -> Result<Vec<String>> {
+    let mut words = vec![];
+    for word in expand_braces(word)? {
+        words.extend(expand_fields(&word)?);
+    }
+    Ok(words)
+}
+
+fn expand_fields(word: &str) -> Result<Vec<String>> {
+    let (expanded, has_quotes) = Expander::new(word).run()?;
+    let fields = split_fields(&expanded);
+    if fields.is_empty() && has_quotes {
+        return Ok(vec![String::new()]);
+    }
+    let mut words = vec![];
+    for field in fields {
+        words.extend(expand_pathname(&field)?);
+    }
+    Ok(words)
+}
+
+/// Expands a word without field splitting or pathname expansion.
+pub fn expand_single(word: &str) -> Result<String> {
+    let (expanded, _) = Expander::new(word).run()?;
+    Ok(expanded.into_iter().map(|(c, _)| c).collect())
+}
+
+/// Expands the value of a `NAME=value` assignment, where a tilde is also
+/// expanded after each unquoted `:`, as in `PATH=~/bin:~user/bin`.
+pub fn expand_assignment(value: &str) -> Result<String> {
+    let mut expander = Expander::new(value);
+    expander.assignment = true;
+    let (expanded, _) = expander.run()?;
+    Ok(to_string(&expanded))
+}
+
+/// Expands a `case` pattern, whose quoted characters match only
+/// themselves.
+pub fn expand_pattern(word: &str) -> Result<Pattern> {
+    Ok(Pattern::new(&expand_operand(word)?))
+}
+
+/// Expands a redirection target without field splitting. A pattern must
+/// match exactly one file, which becomes the target.
+pub fn expand_redirect_target(word: &str) -> Result<String> {
+    let (expanded, _) = Expander::new(word).run()?;
+    let mut paths = expand_pathname(&expanded)?;
+    if paths.len() != 1 {
+        bail!("{}: ambiguous redirect", word);
+    }
+    Ok(paths.remove(0))
+}
+
+/// Expands the body of a here-document whose delimiter is unquoted. Only
+/// parameter expansion, command substitution and arithmetic expansion take
+/// place, and a backslash only escapes `$`, `` ` ``, `\\` and newlines.
+pub fn expand_here_doc(body: &str) -> Result<String> {
+    let mut expander = Expander::new(body);
+    while let Some(c) = expander.next() {
+        match c {
+            '$' => expander.expand_dollar(true)?,
+            '`' => expander.expand_backquote(true)?,
+            BS => match expander.next() {
+                Some('\n') => {}
+                Some(c @ (BS | '$' | '`')) => expander.out.push((c, true)),
+                Some(c) => expander.push_str(&format!("{BS}{c}"), true),
+                None => expander.out.push((BS, true)),
+            },
+            c => expander.out.push((c, true)),
+        }
+    }
+    Ok(to_string(&expander.out))
+}
+
+fn split_fields(expanded: &Expanded) -> Vec<Expanded> {
+    let mut fields = vec![];
+    let mut field: Option<Expanded> = None;
+    for &(c, quoted) in expanded {
+        if !quoted && c.is_whitespace() {
+            fields.extend(field.take());
+        } else {
+            field.get_or_insert_with(Vec::new).push((c, quoted));
+        }
+    }
+    fields.extend(field);
+    fields
+}
+
+/// Replaces a field containing unquoted pattern characters with the paths it
+/// matches. Unmatched patterns are kept as they are unless `nullglob` or
+/// `failglob` is set.
+fn expand_pathname(field: &Expanded) -> Result<Vec<String>> {
+    let word = to_string(field);
+    if !Pattern::new(field).has_wildcards() {
+        return Ok(vec![word]);
+    }
+    let matches = glob(field);
+    if !matches.is_empty() {
+        Ok(matches)
+    } else if shopt_enabled("failglob") {
+        bail!("no match: {}", word)
+    } else if shopt_enabled("nullglob") {
+        Ok(vec![])
+    } else {
+        Ok(vec![word])
+    }
+}
+
+struct Expander {
+    chars: Vec<char>,
+    pos: usize,
+    out: Expanded,
+    has_quotes: bool,
+    assignment: bool,
+}
+
+impl Expander {
+    fn new(word: &str) -> Self {
+        Self {
+            chars: word.chars().collect(),
+            pos: 0,
+            out: vec![],
+            has_quotes: false,
+            assignment: false,
+        }
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.pos += 1;
+        Some(c)
+    }
+
+    fn push_str(&mut self, s: &str, quoted: bool) {
+        self.out.extend(s.chars().map(|c| (c, quoted)));
+    }
+
+    fn run(mut self) -> Result<(Expanded, bool)> {
+        let (mut in_sq, mut in_dq) = (false, false);
+        let mut tilde_allowed = true;
+        while let Some(c) = self.next() {
+            let unquoted = !in_sq && !in_dq;
+            if c == '~' && tilde_allowed && unquoted && self.expand_tilde() {
+                tilde_allowed = false;
+                continue;
+            }
+            tilde_allowed = self.assignment && unquoted && c == ':';
+            match (in_sq, in_dq) {
+                (true, _) => match c {
+                    SQ => in_sq = false,
+                    c => self.out.push((c, true)),
+                },
+                (false, _) if c == '$' => self.expand_dollar(in_dq)?,
+                (false, _) if c == '`' => self.expand_backquote(in_dq)?,
+                (false, false) => match c {
+                    SQ => {
+                        in_sq = true;
+                        self.has_quotes = true;
+                    }
+                    DQ => {
+                        in_dq = true;
+                        self.has_quotes = true;
+                    }
+                    BS => {
+                        let c = self.next().unwrap_or(BS);
+                        self.out.push((c, true));
+                    }
+                    '<' | '>' if self.peek() == Some('(') => {
+                        self.pos += 1;
+                        let source = self.read_matching('(', ')')?;
+                        let path = substitute_process(&source, c == '<')?;
+                        self.push_str(&path, true);
+                    }
+                    c => self.out.push((c, false)),
+                },
+                (false, true) => match c {
+                    DQ => in_dq = false,
+                    BS => match self.next() {
+                        Some(c @ (DQ | BS | '$' | '`')) => self.out.push((c, true)),
+                        Some(c) => self.push_str(&format!("{BS}{c}"), true),
+                        None => self.out.push((BS, true)),
+                    },
+                    c => self.out.push((c, true)),
+                },
+            }
+        }
+        Ok((self.out, self.has_quotes))
+    }
+
+    /// Replaces the tilde prefix following an already consumed `~`, i.e. the
+    /// unquoted characters up to the next `/`, with the directory it names.
+    /// Returns false, consuming nothing, if the prefix is quoted or unknown.
+    fn expand_tilde(&mut self) -> bool {
+        let len = self.chars[self.pos..]
+            .iter()
+            .position(|&c| c == '/' || (self.assignment && c == ':'))
+            .unwrap_or(self.chars.len() - self.pos);
+        let prefix: String = self.chars[self.pos..self.pos + len].iter().collect();
+        if prefix.contains([SQ, DQ, BS, '$', '`']) {
+            return false;
+        }
+        let dir = match prefix.as_str() {
+            "" => get_var("HOME"),
+            "+" => get_var("PWD"),
+            "-" => get_var("OLDPWD"),
+            user => user_home(user),
+        };
+        let Some(dir) = dir else {
+            return false;
+        };
+        self.pos += len;
+        self.push_str(&dir, true);
+        true
+    }
+
+    fn expand_dollar(&mut self, quoted: bool) -> Result<()> {
+        let value = match self.peek() {
+            Some('{') => {
+                self.pos += 1;
+                let inner = self.read_matching('{', '}')?;
+                expand_parameter(&inner)?
+            }
+            Some('(') => {
+                self.pos += 1;
+                match self.read_arithmetic()? {
+                    Some(expr) => {
+                        let value = evaluate(&to_string(&expand_operand(&expr)?))?;
+                        unquoted(Some(&value.to_string()))
+                    }
+                    None => {
+                        let source = self.read_matching('(', ')')?;
+                        unquoted(Some(&capture_output(&source)?))
+                    }
+                }
+            }
+            Some(c) if is_name_start(c) => {
+                let start = self.pos;
+                while self.peek().is_some_and(is_name_char) {
+                    self.pos += 1;
+                }
+                let name: String = self.chars[start..self.pos].iter().collect();
+                unquoted(get_var(&name).as_deref())
+            }
+            Some(c) if is_special_parameter(c) => {
+                self.pos += 1;
+                unquoted(special_parameter(c).as_deref())
+            }
+            _ => unquoted(Some("$")),
+        };
+        self.out
+            .extend(value.into_iter().map(|(c, q)| (c, q || quoted)));
+        Ok(())
+    }
+
+    fn expand_backquote(&mut self, quoted: bool) -> Result<()> {
+        let mut source = String::new();
+        loop {
+            match self.next() {
+                Some('`') => break,
+                // Backslashes only escape `$`, `` ` `` and themselves here.
+                Some(BS) => match self.next() {
+                    Some(c @ ('$' | '`' | BS)) => source.push(c),
+                    Some(c) => {
+                        source.push(BS);
+                        source.push(c);
+                    }
+                    None => source.push(BS),
+                },
+                Some(c) => source.push(c),
+                None => bail!("unexpected EOF while looking for matching ``'"),
+            }
+        }
+        let output = capture_output(&source)?;
+        self.push_str(&output, quoted);
+        Ok(())
+    }
+
+    /// Reads the expression of a `$((...))` whose `$(` is already consumed.
+    /// Leaves the position untouched and returns `None` when the parentheses
+    /// do not end in `))`, as in a command substitution starting with a
+    /// subshell.
+    fn read_arithmetic(&mut self) -> Result<Option<String>> {
+        if self.peek() != Some('(') {
+            return Ok(None);
+        }
+        let start = self.pos;
+        self.pos += 1;
+        let expr = self.read_matching('(', ')')?;
+        if self.peek() == Some(')') {
+            self.pos += 1;
+            return Ok(Some(expr));
+        }
+        self.pos = start;
+        Ok(None)
+    }
+
+    /// Reads up to the `close` matching an already consumed `open`, as in
+    /// `${...}` or `$(...)`, and returns what lies between them.
+    fn read_matching(&mut self, open: char, close: char) -> Result<String> {
+        let start = self.pos;
+        let mut depth = 0;
+        let (mut in_sq, mut in_dq) = (false, false);
+        while let Some(c) = self.next() {
+            match c {
+                BS if !in_sq => self.pos += 1,
+                SQ if !in_dq => in_sq = !in_sq,
+                DQ if !in_sq => in_dq = !in_dq,
+                _ if in_sq || in_dq => {}
+                c if c == open => depth += 1,
+                c if c == close && depth == 0 => {
+                    return Ok(self.chars[start..self.pos - 1].iter().collect());
+                }
+                c if c == close => depth -= 1,
+                _ => {}
+            }
+        }
+        bail!("unexpected EOF while looking for matching `{}`", close)
+    }
+}
+
+/// Looks up a user's home directory in the passwd database.
+fn user_home(user: &str) -> Option<String> {
+    let name = CString::new(user).ok()?;
+    // SAFETY: an all-zero passwd is a valid value for getpwnam_r to fill in.
+    let mut passwd: libc::passwd = unsafe { std::mem::zeroed() };
+    let mut buf = vec![0 as libc::c_char; 16 * 1024];
+    let mut result = std::ptr::null_mut();
+    // SAFETY: every pointer refers to a live buffer of the length given.
+    let status = unsafe {
+        libc::getpwnam_r(
+            name.as_ptr(),
+            &mut passwd,
+            buf.as_mut_ptr(),
+            buf.len(),
+            &mut result,
+        )
+    };
+    if status != 0 || result.is_null() {
+        return None;
+    }
+    // SAFETY: on success pw_dir points to a NUL-terminated string in `buf`.
+    let dir = unsafe { CStr::from_ptr(passwd.pw_dir) };
+    Some(dir.to_string_lossy().into_owned())
+}
+
+fn unquoted(value: Option<&str>) -> Expanded {
+    value
+        .unwrap_or_default()
+        .chars()
+        .map(|c| (c, false))
+        .collect()
+}
+
+fn to_string(expanded: &Expanded) -> String {
+    expanded.iter().map(|(c, _)| c).collect()
+}
+
+/// Expands the word operand of a `${name<op>word}` expansion.
+fn expand_operand(word: &str) -> Result<Expanded> {
+    Expander::new(word).run().map(|(expanded, _)| expanded)
+}
+
+fn is_special_parameter(c: char) -> bool {
+    matches!(c, '?' | '$' | '#' | '!') || c.is_ascii_digit()
+}
+
+fn special_parameter(c: char) -> Option<String> {
+    match c {
+        '?' => Some(last_status().to_string()),
+        '$' => Some(std::process::id().to_string()),
+        '#' => Some(positional_params().len().to_string()),
+        '!' => last_background_pid().map(|pid| pid.to_string()),
+        c => positional_parameter(c.to_digit(10)? as usize),
+    }
+}
+
+/// `$1`, `$2`, ..., which are only set while a function runs. `$0` is
+/// never set.
+fn positional_parameter(index: usize) -> Option<String> {
+    positional_params().get(index.checked_sub(1)?).cloned()
+}
+
+fn lookup(name: &str) -> Option<String> {
+    let mut chars = name.chars();
+    match (chars.next(), chars.as_str()) {
+        (Some(c), "") if is_special_parameter(c) => special_parameter(c),
+        _ if name.bytes().all(|b| b.is_ascii_digit()) => positional_parameter(name.parse().ok()?),
+        _ => get_var(name),
+    }
+}
+
+/// Splits `${...}` contents into the parameter name and whatever follows it.
+fn split_parameter_name(inner: &str) -> Option<(&str, &str)> {
+    let first = inner.chars().next()?;
+    let len = if first.is_ascii_digit() {
+        inner
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(inner.len())
+    } else if is_special_parameter(first) {
+        1
+    } else if is_name_start(first) {
+        inner.find(|c| !is_name_char(c)).unwrap_or(inner.len())
+    } else {
+        return None;
+    };
+    Some(inner.split_at(len))
+}
+
+// Ordered so that longer operators are tried before their prefixes.
+const PARAMETER_OPERATORS: &[&str] = &[
+    ":-", ":=", ":?", ":+", "-", "=", "?", "+", "##", "#", "%%", "%", "//", "/#", "/%", "/", ":",
+];
+
+fn expand_parameter(inner: &str) -> Result<Expanded> {
+    let bad_substitution = || anyhow!("${{{}}}: bad substitution", inner);
+    if let Some(name) = inner.strip_prefix('#').filter(|name| !name.is_empty()) {
+        match split_parameter_name(name) {
+            Some((name, "")) => {
+                let len = lookup(name).unwrap_or_default().chars().count();
+                return Ok(unquoted(Some(&len.to_string())));
+            }
+            _ => return Err(bad_substitution()),
+        }
+    }
+    let (name, rest) = split_parameter_name(inner).ok_or_else(bad_substitution)?;
+    let value = lookup(name);
+    if rest.is_empty() {
+        return Ok(unquoted(value.as_deref()));
+    }
+    let (op, word) = PARAMETER_OPERATORS
+        .iter()
+        .find_map(|op| rest.strip_prefix(op).map(|word| (*op, word)))
+        .ok_or_else(bad_substitution)?;
+    // With a colon, the operators treat an empty value like an unset one.
+    let missing = match value.as_deref() {
+        None => true,
+        Some(value) => value.is_empty() && op.starts_with(':'),
+    };
+    match op {
+        ":-" | "-" if missing => expand_operand(word),
+        ":=" | "=" if missing => {
+            if !is_valid_name(name) {
+                bail!("${}: cannot assign in this way", name);
+            }
+            let expanded = expand_operand(word)?;
+            set_var(name, &to_string(&expanded))?;
+            Ok(expanded)
+        }
+        ":?" | "?" if missing => match to_string(&expand_operand(word)?) {
+            message if message.is_empty() => bail!("{}: parameter null or not set", name),
+            message => bail!("{}: {}", name, message),
+        },
+        ":+" | "+" if missing => Ok(vec![]),
+        ":+" | "+" => expand_operand(word),
+        "#" | "##" | "%" | "%%" => {
+            let pattern = Pattern::new(&expand_operand(word)?);
+            let value = value.unwrap_or_default();
+            Ok(unquoted(Some(&remove_affix(&value, &pattern, op))))
+        }
+        "//" | "/#" | "/%" | "/" => {
+            let (pattern, replacement) = split_unquoted(word, '/');
+            let pattern = Pattern::new(&expand_operand(pattern)?);
+            let replacement = to_string(&expand_operand(replacement.unwrap_or_default())?);
+            let value = value.unwrap_or_default();
+            Ok(unquoted(Some(&substitute(
+                &value,
+                &pattern,
+                &replacement,
+                op,
+            ))))
+        }
+        ":" => {
+            let value = value.unwrap_or_default();
+            Ok(unquoted(Some(&substring(&value, word)?)))
+        }
+        _ => Ok(unquoted(value.as_deref())),
+    }
+}
+
+/// Splits a word at the first unquoted occurrence of `delim`.
+fn split_unquoted(word: &str, delim: char) -> (&str, Option<&str>) {
+    let (mut in_sq, mut in_dq, mut escaped) = (false, false, false);
+    for (i, c) in word.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            BS if !in_sq => escaped = true,
+            SQ if !in_dq => in_sq = !in_sq,
+            DQ if !in_sq => in_dq = !in_dq,
+            c if c == delim && !in_sq && !in_dq => {
+                return (&word[..i], Some(&word[i + c.len_utf8()..]));
+            }
+            _ => {}
+        }
+    }
+    (word, None)
+}
+
+/// Implements `#`/`##` (shortest/longest prefix) and `%`/`%%` (shortest/
+/// longest suffix) removal.
+fn remove_affix(value: &str, pattern: &Pattern, op: &str) -> String {
+    let chars: Vec<char> = value.chars().collect();
+    let len = chars.len();
+    let matched = match op {
+        "#" => (0..=len).find(|&i| pattern.matches_chars(&chars[..i])),
+        "##" => (0..=len)
+            .rev()
+            .find(|&i| pattern.matches_chars(&chars[..i])),
+        "%" => (0..=len)
+            .rev()
+            .find(|&i| pattern.matches_chars(&chars[i..])),
+        _ => (0..=len).find(|&i| pattern.matches_chars(&chars[i..])),
+    };
+    match (op, matched) {
+        ("#" | "##", Some(i)) => chars[i..].iter().collect(),
+        (_, Some(i)) => chars[..i].iter().collect(),
+        (_, None) => value.to_string(),
+    }
+}
+
+/// Implements `/` (first match), `//` (every match), `/#` (match anchored at
+/// the start) and `/%` (match anchored at the end), always preferring the
+/// longest match.
+fn substitute(value: &str, pattern: &Pattern, replacement: &str, op: &str) -> String {
+    let chars: Vec<char> = value.chars().collect();
+    let len = chars.len();
+    if pattern.is_empty() {
+        return value.to_string();
+    }
+    let longest_from = |start: usize| {
+        (start..=len)
+            .rev()
+            .find(|&end| pattern.matches_chars(&chars[start..end]))
+    };
+    let (start, end) = match op {
+        "/#" => match longest_from(0) {
+            Some(end) => (0, end),
+            None => return value.to_string(),
+        },
+        "/%" => match (0..=len).find(|&start| pattern.matches_chars(&chars[start..])) {
+            Some(start) => (start, len),
+            None => return value.to_string(),
+        },
+        _ => {
+            let mut out = String::new();
+            let mut pos = 0;
+            let mut replaced = false;
+            while pos < len {
+                match longest_from(pos).filter(|&end| end > pos) {
+                    Some(end) if op == "//" || !replaced => {
+                        out.push_str(replacement);
+                        pos = end;
+                        replaced = true;
+                    }
+                    _ => {
+                        out.push(chars[pos]);
+                        pos += 1;
+                    }
+                }
+            }
+            return out;
+        }
+    };
+    let prefix: String = chars[..start].iter().collect();
+    let suffix: String = chars[end..].iter().collect();
+    format!("{}{}{}", prefix, replacement, suffix)
+}
+
+/// Implements `${name:offset}` and `${name:offset:length}`. Negative values
+/// count from the end of the value.
+fn substring(value: &str, word: &str) -> Result<String> {
+    let chars: Vec<char> = value.chars().collect();
+    let len = chars.len() as i64;
+    let parse = |s: &str| evaluate(&to_string(&expand_operand(s)?));
+    let (offset, length) = match word.split_once(':') {
+        Some((offset, length)) => (parse(offset)?, Some(parse(length)?)),
+        None => (parse(word)?, None),
+    };
+    let start = if offset < 0 { len + offset } else { offset };
+    if start < 0 || start > len {
+        return Ok(String::new());
+    }
+    let end = match length {
+        None => len,
+        Some(length) if length < 0 => len + length,
+        Some(length) => start.saturating_add(length).min(len),
+    };
+    if end < start {
+        bail!("{}: substring expression < 0", word);
+    }
+    Ok(chars[start as usize..end as usize].iter().collect())
+}
@@ -0,0 +1,43 @@
+use once_cell::sync::Lazy;
+use std::{
+    collections::BTreeMap,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex,
+    },
+};
+
+use crate::parser::FunctionDef;
+
+/// Functions defined with `name() compound-command`, by name.
+static FUNCTIONS: Lazy<Mutex<BTreeMap<String, FunctionDef>>> =
+    Lazy::new(|| Mutex::new(BTreeMap::new()));
+
+/// How many function calls are running, one inside another.
+static CALL_DEPTH: AtomicUsize = AtomicUsize::new(0);
+
+/// Defines a function, replacing any earlier one of the same name.
+pub fn define_function(def: FunctionDef) {
+    FUNCTIONS.lock().unwrap().insert(def.name.clone(), def);
+}
+
+pub fn get_function(name: &str) -> Option<FunctionDef> {
+    FUNCTIONS.lock().unwrap().get(name).cloned()
+}
+
+pub fn is_function(name: &str) -> bool {
+    FUNCTIONS.lock().unwrap().contains_key(name)
+}
+
+/// Whether a function is running, which `return` requires.
+pub fn in_function() -> bool {
+    CALL_DEPTH.load(Ordering::Relaxed) > 0
+}
+
+/// Counts a function call for as long as `run` takes.
+pub fn track_call<T>(run: impl FnOnce() -> T) -> T {
+    CALL_DEPTH.fetch_add(1, Ordering::Relaxed);
+    let result = run();
+    CALL_DEPTH.fetch_sub(1, Ordering::Relaxed);
+    result
+}
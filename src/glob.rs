@@ -0,0 +1,101 @@
+use std::{collections::HashSet, fs, path::PathBuf};
+
+use crate::{options::shopt_enabled, pattern::Pattern};
+
+type Component<'a> = &'a [(char, bool)];
+
+/// Expands a pathname pattern against the filesystem and returns the
+/// matching paths in sorted order. Each `/`-separated component is matched
+/// separately; files starting with a dot only match a component that starts
+/// with a literal dot. With `shopt -s globstar`, a `**` component matches any
+/// number of directories.
+pub fn glob(pattern: &[(char, bool)]) -> Vec<String> {
+    let components: Vec<Component> = pattern.split(|&(c, _)| c == '/').collect();
+    let (prefix, components) = match components.split_first() {
+        Some(([], rest)) => ("/".to_string(), rest),
+        _ => (String::new(), components.as_slice()),
+    };
+    let mut matches = vec![];
+    expand_components(prefix, components, &mut matches);
+    matches.sort();
+    matches
+}
+
+fn expand_components(prefix: String, components: &[Component], matches: &mut Vec<String>) {
+    let Some((component, rest)) = components.split_first() else {
+        if fs::symlink_metadata(&prefix).is_ok() {
+            matches.push(prefix);
+        }
+        return;
+    };
+    if component == &[('*', false), ('*', false)] && shopt_enabled("globstar") {
+        if rest.is_empty() && !prefix.is_empty() {
+            matches.push(prefix.clone());
+        }
+        expand_globstar(prefix, rest, matches, &mut HashSet::new());
+        return;
+    }
+    let separator = if rest.is_empty() { "" } else { "/" };
+    let pattern = Pattern::new(component);
+    if !pattern.has_wildcards() {
+        let name: String = component.iter().map(|(c, _)| c).collect();
+        expand_components(format!("{prefix}{name}{separator}"), rest, matches);
+        return;
+    }
+    let dir = if prefix.is_empty() { "." } else { &prefix };
+    let Ok(entries) = fs::read_dir(dir) else {
+        return;
+    };
+    let match_hidden = matches!(component.first(), Some(('.', _)));
+    for entry in entries.flatten() {
+        let Ok(name) = entry.file_name().into_string() else {
+            continue;
+        };
+        if (name.starts_with('.') && !match_hidden) || !pattern.matches(&name) {
+            continue;
+        }
+        if !rest.is_empty() && !entry.path().is_dir() {
+            continue;
+        }
+        expand_components(format!("{prefix}{name}{separator}"), rest, matches);
+    }
+}
+
+/// Matches `rest` in `prefix` and every directory below it. A lone `**`
+/// matches all the files and directories themselves. Directories reached
+/// through symlinks are followed once, so that loops end.
+fn expand_globstar(
+    prefix: String,
+    rest: &[Component],
+    matches: &mut Vec<String>,
+    visited: &mut HashSet<PathBuf>,
+) {
+    let dir = if prefix.is_empty() { "." } else { &prefix };
+    let Ok(canonical) = fs::canonicalize(dir) else {
+        return;
+    };
+    if !visited.insert(canonical) {
+        return;
+    }
+    if !rest.is_empty() {
+        expand_components(prefix.clone(), rest, matches);
+    }
+    let Ok(entries) = fs::read_dir(dir) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let Ok(name) = entry.file_name().into_string() else {
+            continue;
+        };
+        if name.starts_with('.') {
+            continue;
+        }
+        let path = format!("{prefix}{name}");
+        if rest.is_empty() {
+            matches.push(path.clone());
+        }
+        if entry.path().is_dir() {
+            expand_globstar(format!("{path}/"), rest, matches, visited);
+        }
+    }
+}
@@ -1,9 +1,12 @@
-use anyhow::Result;
+use anyhow::{Context, Result};
 use once_cell::sync::Lazy;
 use std::io::Write;
 use std::{collections::VecDeque, sync::Mutex};
 
-use crate::context::{create_file_writer, CommandContext};
+use crate::{
+    context::{create_file_writer, CommandContext},
+    vars::get_var,
+};
 
 const MAX_HISTORY_RETAINED: usize = 100;
 const HISTFILE: &str = "HISTFILE";
@@ -32,7 +35,7 @@ impl Default for CommandHistory {
 }
 
 fn init_from_file() -> Result<VecDeque<String>> {
-    let read_path = std::env::var(HISTFILE)?;
+    let read_path = get_var(HISTFILE).context("HISTFILE is not set")?;
     let content = std::fs::read_to_string(read_path)?;
     Ok(content.lines().map(String::from).collect())
 }
@@ -71,14 +74,29 @@ impl CommandHistory {
         history.data.push_back(command.to_string());
     }
 
-    pub fn handle_command(ctx: &CommandContext) {
-        match HistoryArgs::from(&ctx.args) {
-            HistoryArgs::None => print_history(ctx, None),
-            HistoryArgs::Limit(n) => print_history(ctx, Some(n)),
-            HistoryArgs::ReadFile(path) => read_history_file(path),
+    /// Runs the `history` builtin and returns its exit status.
+    pub fn handle_command(ctx: &CommandContext) -> i32 {
+        let result = match HistoryArgs::from(&ctx.args) {
+            HistoryArgs::None => {
+                print_history(ctx, None);
+                Ok(())
+            }
+            HistoryArgs::Limit(n) => {
+                print_history(ctx, Some(n));
+                Ok(())
+            }
+            HistoryArgs::ReadFile(path) => {
+                read_history_file(path);
+                Ok(())
+            }
             HistoryArgs::WriteFile(path) => write_history_file(path, false),
             HistoryArgs::AppendFile(path) => write_history_file(path, true),
+        };
+        if let Err(e) = result {
+            let _ = ctx.ewriteln(format_args!("history: {}", e));
+            return 1;
         }
+        0
     }
 
     pub fn reset_browse() {
@@ -119,10 +137,8 @@ fn read_history_file(path: String) {
     }
 }
 
-fn write_history_file(path: String, append: bool) {
-    let Some(mut file) = create_file_writer(&path, append) else {
-        return;
-    };
+fn write_history_file(path: String, append: bool) -> Result<()> {
+    let mut file = create_file_writer(&path, append)?;
     let mut history = COMMAND_HISTORY.lock().unwrap();
     let mut skip_count = 0;
     if append {
@@ -130,13 +146,14 @@ fn write_history_file(path: String, append: bool) {
         history.last_append_idx = Some(history.data.len() - 1);
     }
     for entry in history.data.iter().skip(skip_count) {
-        let _ = writeln!(file, "{}", entry);
+        writeln!(file, "{}", entry)?;
     }
+    Ok(())
 }
 
 pub fn write_history_on_exit() {
-    if let Ok(path) = std::env::var(HISTFILE) {
-        write_history_file(path, true);
+    if let Some(path) = get_var(HISTFILE) {
+        let _ = write_history_file(path, true);
     }
 }
 
@@ -0,0 +1,151 @@
+use crate::exec::wait_forked;
+use anyhow::{anyhow, bail, Result};
+use once_cell::sync::Lazy;
+use std::{
+    collections::BTreeMap,
+    ffi::CStr,
+    sync::{
+        atomic::{AtomicI32, Ordering},
+        Mutex,
+    },
+};
+
+/// A list started in the background with `&`.
+struct Job {
+    id: usize,
+    pid: i32,
+    command: String,
+}
+
+static JOBS: Lazy<Mutex<Vec<Job>>> = Lazy::new(|| Mutex::new(vec![]));
+
+/// Exit statuses of the jobs reported finished, by process ID, which `wait`
+/// can still ask for.
+static REAPED: Lazy<Mutex<BTreeMap<i32, i32>>> = Lazy::new(|| Mutex::new(BTreeMap::new()));
+
+/// Process ID of the most recent background job, exposed as `$!`. Zero
+/// until a job has been started.
+static LAST_BACKGROUND_PID: AtomicI32 = AtomicI32::new(0);
+
+/// Adds a job to the table and returns its job number, which is one more
+/// than the highest number in use.
+pub fn add_job(pid: i32, command: String) -> usize {
+    let mut jobs = JOBS.lock().unwrap();
+    let id = jobs.last().map_or(1, |job| job.id + 1);
+    jobs.push(Job { id, pid, command });
+    LAST_BACKGROUND_PID.store(pid, Ordering::Relaxed);
+    id
+}
+
+pub fn last_background_pid() -> Option<i32> {
+    match LAST_BACKGROUND_PID.load(Ordering::Relaxed) {
+        0 => None,
+        pid => Some(pid),
+    }
+}
+
+/// Removes the jobs that have finished from the table and returns a line
+/// reporting each of them, such as `[1]+  Done                    sleep 1`.
+/// The most recent job is marked with `+` and the one before it with `-`.
+pub fn take_finished_jobs() -> Vec<String> {
+    report_jobs(false)
+}
+
+/// Reports every job for `jobs`, running ones as
+/// `[1]+  Running                 sleep 1 &`, and removes those that have
+/// finished as `take_finished_jobs` does.
+pub fn list_jobs() -> Vec<String> {
+    report_jobs(true)
+}
+
+fn report_jobs(running: bool) -> Vec<String> {
+    let mut jobs = JOBS.lock().unwrap();
+    let count = jobs.len();
+    let mut reports = vec![];
+    let mut index = 0;
+    jobs.retain(|job| {
+        let marker = match count - index {
+            1 => '+',
+            2 => '-',
+            _ => ' ',
+        };
+        index += 1;
+        let mut status = 0;
+        // SAFETY: waitpid only writes to the status it is given.
+        if unsafe { libc::waitpid(job.pid, &mut status, libc::WNOHANG) } != job.pid {
+            if running {
+                reports.push(format!(
+                    "[{}]{}  {:<24}{} &",
+                    job.id, marker, "Running", job.command
+                ));
+            }
+            return true;
+        }
+        let state = describe_status(status);
+        let code = if libc::WIFSIGNALED(status) {
+            128 + libc::WTERMSIG(status)
+        } else {
+            libc::WEXITSTATUS(status)
+        };
+        REAPED.lock().unwrap().insert(job.pid, code);
+        reports.push(format!(
+            "[{}]{}  {:<24}{}",
+            job.id, marker, state, job.command
+        ));
+        false
+    });
+    reports
+}
+
+/// Waits for the job given as `%n` or by its process ID, removes it from the
+/// table, and returns its exit status. A job already reported finished is
+/// only found by its process ID.
+pub fn wait_job(spec: &str) -> Result<i32> {
+    let mut jobs = JOBS.lock().unwrap();
+    let index = match spec.strip_prefix('%') {
+        Some(id) => id
+            .parse::<usize>()
+            .ok()
+            .and_then(|id| jobs.iter().position(|job| job.id == id))
+            .ok_or_else(|| anyhow!("{}: no such job", spec))?,
+        None => {
+            let Ok(pid) = spec.parse::<i32>() else {
+                bail!("`{}': not a pid or valid job spec", spec);
+            };
+            match jobs.iter().position(|job| job.pid == pid) {
+                Some(index) => index,
+                None => {
+                    return REAPED
+                        .lock()
+                        .unwrap()
+                        .remove(&pid)
+                        .ok_or_else(|| anyhow!("pid {} is not a child of this shell", pid))
+                }
+            }
+        }
+    };
+    let job = jobs.remove(index);
+    drop(jobs);
+    Ok(wait_forked(job.pid))
+}
+
+/// Waits for every job and empties the table.
+pub fn wait_all_jobs() {
+    let jobs = std::mem::take(&mut *JOBS.lock().unwrap());
+    for job in jobs {
+        wait_forked(job.pid);
+    }
+}
+
+/// Describes how a job ended: `Done`, `Exit N`, or the signal that ended it.
+fn describe_status(status: i32) -> String {
+    if libc::WIFSIGNALED(status) {
+        // SAFETY: strsignal returns a valid string for any signal number.
+        let name = unsafe { CStr::from_ptr(libc::strsignal(libc::WTERMSIG(status))) };
+        return name.to_string_lossy().into_owned();
+    }
+    match libc::WEXITSTATUS(status) {
+        0 => "Done".to_string(),
+        code => format!("Exit {}", code),
+    }
+}
@@ -0,0 +1,449 @@
+use anyhow::{bail, Result};
+use std::fmt::Display;
+
+pub(crate) const SQ: char = '\'';
+pub(crate) const DQ: char = '"';
+pub(crate) const BS: char = '\\';
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Token {
+    /// A word with its quoting left intact, e.g. `'a|b'` or `"$HOME"/x`.
+    Word(String),
+    /// Digits directly preceding a redirection operator, as in `2>`.
+    IoNumber(u32),
+    /// The expression of a `((...))` arithmetic command.
+    Arith(String),
+    /// The delimiter word following `<<` or `<<-`, as written, together with
+    /// the here-document it ends.
+    HereDoc(String, HereDoc),
+    Op(Operator),
+}
+
+/// The lines following a command up to the one holding just the delimiter
+/// of a `<<` redirection.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct HereDoc {
+    pub body: String,
+    /// False when any part of the delimiter is quoted, in which case the
+    /// body is used exactly as written.
+    pub expand: bool,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Operator {
+    Pipe,
+    PipeAnd,
+    AndIf,
+    OrIf,
+    Amp,
+    Semi,
+    /// `;;`, which ends an item of a `case` command.
+    DSemi,
+    Newline,
+    LParen,
+    RParen,
+    Less,
+    Great,
+    DGreat,
+    Clobber,
+    LessGreat,
+    DLess,
+    DLessDash,
+    TLess,
+    LessAnd,
+    GreatAnd,
+    AndGreat,
+    AndDGreat,
+}
+
+// Ordered longest first so that matching can stop at the first hit.
+const OPERATORS: &[(&str, Operator)] = &[
+    ("&>>", Operator::AndDGreat),
+    ("<<<", Operator::TLess),
+    ("<<-", Operator::DLessDash),
+    ("&&", Operator::AndIf),
+    ("||", Operator::OrIf),
+    ("|&", Operator::PipeAnd),
+    (";;", Operator::DSemi),
+    (">>", Operator::DGreat),
+    (">|", Operator::Clobber),
+    ("<>", Operator::LessGreat),
+    ("<<", Operator::DLess),
+    ("<&", Operator::LessAnd),
+    (">&", Operator::GreatAnd),
+    ("&>", Operator::AndGreat),
+    ("|", Operator::Pipe),
+    ("&", Operator::Amp),
+    (";", Operator::Semi),
+    ("(", Operator::LParen),
+    (")", Operator::RParen),
+    ("<", Operator::Less),
+    (">", Operator::Great),
+    ("\n", Operator::Newline),
+];
+
+impl Operator {
+    pub fn as_str(&self) -> &'static str {
+        OPERATORS
+            .iter()
+            .find_map(|(s, op)| (op == self).then_some(*s))
+            .unwrap_or_default()
+    }
+}
+
+impl Display for Operator {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Operator::Newline => write!(f, "newline"),
+            op => write!(f, "{}", op.as_str()),
+        }
+    }
+}
+
+pub fn tokenize(input: &str) -> Result<Vec<Token>> {
+    Lexer::new(input).run().map(|lexer| lexer.tokens)
+}
+
+/// Whether the input ends before the delimiter of one of its here-documents,
+/// so that more lines have to be read before it can run.
+pub fn needs_more_input(input: &str) -> bool {
+    Lexer::new(input).run().is_ok_and(|lexer| lexer.incomplete)
+}
+
+fn is_operator_start(c: char) -> bool {
+    matches!(c, '|' | '&' | ';' | '<' | '>' | '(' | ')' | '\n')
+}
+
+/// Removes the quotes from a here-document delimiter.
+fn remove_quotes(word: &str) -> String {
+    let mut chars = word.chars();
+    let mut out = String::new();
+    let mut quote = None;
+    while let Some(c) = chars.next() {
+        match (c, quote) {
+            (SQ, None) | (DQ, None) => quote = Some(c),
+            (c, Some(q)) if c == q => quote = None,
+            (BS, Some(SQ)) => out.push(BS),
+            (BS, _) => out.extend(chars.next()),
+            (c, _) => out.push(c),
+        }
+    }
+    out
+}
+
+struct Lexer {
+    chars: Vec<char>,
+    pos: usize,
+    word: String,
+    tokens: Vec<Token>,
+    /// Set after `<<` or `<<-` until the delimiter word is read; true when
+    /// leading tabs are to be stripped from the body.
+    delimiter_due: Option<bool>,
+    /// Here-documents whose bodies start after the next newline, as the
+    /// index of their token and whether to strip tabs.
+    pending: Vec<(usize, bool)>,
+    /// Whether the input ended before a here-document's delimiter.
+    incomplete: bool,
+}
+
+impl Lexer {
+    fn new(input: &str) -> Self {
+        Self {
+            chars: input.chars().collect(),
+            pos: 0,
+            word: String::new(),
+            tokens: vec![],
+            delimiter_due: None,
+            pending: vec![],
+            incomplete: false,
+        }
+    }
+
+    fn run(mut self) -> Result<Self> {
+        while let Some(c) = self.peek() {
+            match c {
+                '(' if self.word.is_empty() && self.read_arith_command() => {}
+                // Extended patterns such as `@(a|b)` stay part of the word.
+                '(' if self.word.ends_with(['?', '*', '+', '@', '!']) => {
+                    self.pos += 1;
+                    self.word.push('(');
+                    self.read_until(')')?;
+                }
+                // Process substitutions such as `<(cmd)` are words.
+                '<' | '>' if self.word.is_empty() && self.chars.get(self.pos + 1) == Some(&'(') => {
+                    self.word.push(c);
+                    self.word.push('(');
+                    self.pos += 2;
+                    self.read_until(')')?;
+                }
+                c if is_operator_start(c) => self.read_operator(),
+                c if c.is_whitespace() => {
+                    self.pos += 1;
+                    self.delimit();
+                }
+                '#' if self.word.is_empty() => self.skip_comment(),
+                SQ => self.read_single_quoted()?,
+                DQ => self.read_double_quoted()?,
+                BS => self.read_escape(),
+                '$' => self.read_dollar()?,
+                '`' => self.read_backquoted()?,
+                c => {
+                    self.pos += 1;
+                    self.word.push(c);
+                }
+            }
+        }
+        self.delimit();
+        self.read_here_docs();
+        Ok(self)
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.pos += 1;
+        Some(c)
+    }
+
+    fn delimit(&mut self) {
+        if self.word.is_empty() {
+            return;
+        }
+        let word = std::mem::take(&mut self.word);
+        match self.delimiter_due.take() {
+            Some(strip_tabs) => {
+                let expand = !word.contains([SQ, DQ, BS]);
+                let doc = HereDoc {
+                    body: String::new(),
+                    expand,
+                };
+                self.pending.push((self.tokens.len(), strip_tabs));
+                self.tokens.push(Token::HereDoc(word, doc));
+            }
+            None => self.tokens.push(Token::Word(word)),
+        }
+    }
+
+    /// Reads the bodies of the here-documents started on the line just
+    /// ended, one after another, each up to the line holding only its
+    /// delimiter.
+    fn read_here_docs(&mut self) {
+        for (index, strip_tabs) in std::mem::take(&mut self.pending) {
+            let Token::HereDoc(word, _) = &self.tokens[index] else {
+                unreachable!("pending here-document without its token");
+            };
+            let delimiter = remove_quotes(word);
+            let mut body = String::new();
+            loop {
+                if self.pos >= self.chars.len() {
+                    self.incomplete = true;
+                    break;
+                }
+                let mut line = String::new();
+                while let Some(c) = self.next() {
+                    if c == '\n' {
+                        break;
+                    }
+                    line.push(c);
+                }
+                let line = match strip_tabs {
+                    true => line.trim_start_matches('\t'),
+                    false => &line,
+                };
+                if line == delimiter {
+                    break;
+                }
+                body.push_str(line);
+                body.push('\n');
+            }
+            if let Token::HereDoc(_, doc) = &mut self.tokens[index] {
+                doc.body = body;
+            }
+        }
+    }
+
+    fn read_operator(&mut self) {
+        let rest: String = self.chars[self.pos..].iter().take(3).collect();
+        let (s, op) = OPERATORS
+            .iter()
+            .find(|(s, _)| rest.starts_with(s))
+            .expect("operator start without a matching operator");
+        self.pos += s.chars().count();
+        let io_number = self
+            .word
+            .bytes()
+            .all(|b| b.is_ascii_digit())
+            .then(|| self.word.parse::<u32>().ok())
+            .flatten();
+        match io_number {
+            Some(fd) if s.starts_with(['<', '>']) => {
+                self.word.clear();
+                self.tokens.push(Token::IoNumber(fd));
+            }
+            _ => self.delimit(),
+        }
+        self.tokens.push(Token::Op(*op));
+        match op {
+            Operator::DLess | Operator::DLessDash => {
+                self.delimiter_due = Some(*op == Operator::DLessDash);
+            }
+            Operator::Newline => self.read_here_docs(),
+            _ => {}
+        }
+    }
+
+    /// Reads a `((...))` arithmetic command. Returns false, consuming
+    /// nothing, when the parentheses do not close with `))`, so that
+    /// `((cmd) ...)` still lexes as nested subshells.
+    fn read_arith_command(&mut self) -> bool {
+        if self.chars.get(self.pos + 1) != Some(&'(') {
+            return false;
+        }
+        let start = self.pos + 2;
+        let mut depth = 0;
+        let mut quote = None;
+        for (i, &c) in self.chars.iter().enumerate().skip(start) {
+            match c {
+                SQ | DQ if quote == Some(c) => quote = None,
+                _ if quote.is_some() => {}
+                SQ | DQ => quote = Some(c),
+                '(' => depth += 1,
+                ')' if depth > 0 => depth -= 1,
+                ')' if self.chars.get(i + 1) == Some(&')') => {
+                    let expr = self.chars[start..i].iter().collect();
+                    self.tokens.push(Token::Arith(expr));
+                    self.pos = i + 2;
+                    return true;
+                }
+                ')' => return false,
+                _ => {}
+            }
+        }
+        false
+    }
+
+    fn skip_comment(&mut self) {
+        while self.peek().is_some_and(|c| c != '\n') {
+            self.pos += 1;
+        }
+    }
+
+    fn read_escape(&mut self) {
+        self.pos += 1;
+        match self.next() {
+            // A backslash-newline is a line continuation and vanishes entirely.
+            Some('\n') => {}
+            Some(c) => {
+                self.word.push(BS);
+                self.word.push(c);
+            }
+            None => self.word.push(BS),
+        }
+    }
+
+    fn read_single_quoted(&mut self) -> Result<()> {
+        self.pos += 1;
+        self.word.push(SQ);
+        loop {
+            match self.next() {
+                Some(SQ) => break,
+                Some(c) => self.word.push(c),
+                None => bail!("unexpected EOF while looking for matching `{}`", SQ),
+            }
+        }
+        self.word.push(SQ);
+        Ok(())
+    }
+
+    fn read_double_quoted(&mut self) -> Result<()> {
+        self.pos += 1;
+        self.word.push(DQ);
+        loop {
+            match self.peek() {
+                Some(DQ) => break,
+                Some(BS) => {
+                    self.pos += 1;
+                    self.word.push(BS);
+                    if let Some(c) = self.next() {
+                        self.word.push(c);
+                    }
+                }
+                Some('$') => self.read_dollar()?,
+                Some('`') => self.read_backquoted()?,
+                Some(c) => {
+                    self.pos += 1;
+                    self.word.push(c);
+                }
+                None => bail!("unexpected EOF while looking for matching `{}`", DQ),
+            }
+        }
+        self.pos += 1;
+        self.word.push(DQ);
+        Ok(())
+    }
+
+    fn read_dollar(&mut self) -> Result<()> {
+        self.pos += 1;
+        self.word.push('$');
+        let close = match self.peek() {
+            Some('{') => '}',
+            Some('(') => ')',
+            _ => return Ok(()),
+        };
+        self.word.push(self.chars[self.pos]);
+        self.pos += 1;
+        self.read_until(close)
+    }
+
+    fn read_backquoted(&mut self) -> Result<()> {
+        self.pos += 1;
+        self.word.push('`');
+        loop {
+            match self.next() {
+                Some('`') => break,
+                Some(BS) => {
+                    self.word.push(BS);
+                    if let Some(c) = self.next() {
+                        self.word.push(c);
+                    }
+                }
+                Some(c) => self.word.push(c),
+                None => bail!("unexpected EOF while looking for matching ``'"),
+            }
+        }
+        self.word.push('`');
+        Ok(())
+    }
+
+    /// Copies characters up to and including `close`, stepping over quotes
+    /// and nested expansions so that their contents cannot end the word.
+    fn read_until(&mut self, close: char) -> Result<()> {
+        loop {
+            match self.peek() {
+                Some(c) if c == close => {
+                    self.pos += 1;
+                    self.word.push(c);
+                    return Ok(());
+                }
+                Some(SQ) => self.read_single_quoted()?,
+                Some(DQ) => self.read_double_quoted()?,
+                Some(BS) => self.read_escape(),
+                Some('$') => self.read_dollar()?,
+                Some('`') => self.read_backquoted()?,
+                Some('(') if close == ')' => {
+                    self.pos += 1;
+                    self.word.push('(');
+                    self.read_until(')')?;
+                }
+                Some(c) => {
+                    self.pos += 1;
+                    self.word.push(c);
+                }
+                None => bail!("unexpected EOF while looking for matching `{}`", close),
+            }
+        }
+    }
+}
@@ -1,5 +1,16 @@
-pub mod args;
+pub mod arith;
+pub mod brace;
 pub mod command;
 pub mod context;
 pub mod editor;
+pub mod exec;
+pub mod expand;
+pub mod functions;
+pub mod glob;
 pub mod history;
+pub mod jobs;
+pub mod lexer;
+pub mod options;
+pub mod parser;
+pub mod pattern;
+pub mod vars;
@@ -1,101 +1,53 @@
-use anyhow::{Context, Result};
-use codecrafters_shell::command::{handle_command, Command};
-use codecrafters_shell::context::{create_file_writer, parse_commands, CommandContext, Writer};
+use anyhow::Result;
+use codecrafters_shell::command::ExitRequest;
+use codecrafters_shell::context::Io;
 use codecrafters_shell::editor::get_editor;
-use codecrafters_shell::history::CommandHistory;
-use std::cell::RefCell;
-use std::process::{Child, Command as ProcessCommand, Stdio};
-use std::thread::{self};
+use codecrafters_shell::exec::execute_commands;
+use codecrafters_shell::history::{write_history_on_exit, CommandHistory};
+use codecrafters_shell::jobs::take_finished_jobs;
+use codecrafters_shell::lexer::needs_more_input;
+use codecrafters_shell::parser::{is_incomplete, parse};
+use codecrafters_shell::vars::set_last_status;
+use rustyline::error::ReadlineError;
 
 fn main() -> Result<()> {
     let mut editor = get_editor();
     loop {
         CommandHistory::reset_browse();
-        let input = editor.readline("$ ")?;
+        for report in take_finished_jobs() {
+            eprintln!("{}", report);
+        }
+        let mut input = editor.readline("$ ")?;
+        while is_incomplete(&input) {
+            match editor.readline("> ") {
+                Ok(line) => {
+                    input.push('\n');
+                    input.push_str(&line);
+                }
+                Err(ReadlineError::Eof) => {
+                    if needs_more_input(&input) {
+                        eprintln!("warning: here-document delimited by end-of-file");
+                    }
+                    break;
+                }
+                Err(e) => return Err(e.into()),
+            }
+        }
         CommandHistory::add(&input);
-        let commands = match parse_commands(&input) {
-            Ok(ctx) => ctx,
+        let list = match parse(&input) {
+            Ok(list) => list,
             Err(e) => {
                 eprintln!("Error processing input: {}", e);
+                set_last_status(2);
                 continue;
             }
         };
-        execute_commands(commands)?;
-    }
-}
-
-fn execute_commands(contexts: Vec<CommandContext>) -> Result<()> {
-    let mut prev_reader = None;
-    let mut handles = vec![];
-    let mut children = vec![];
-    let last_idx = contexts.len() - 1;
-    for (idx, mut ctx) in contexts.into_iter().enumerate() {
-        if let Some(prev) = prev_reader.take() {
-            ctx.piped_stdin = Some(prev);
-        }
-        if idx != last_idx {
-            let (reader, writer) = os_pipe::pipe()?;
-            ctx.writer = RefCell::new(Writer::Pipe(writer));
-            prev_reader = Some(reader);
-        }
-        match &ctx.command {
-            Command::Executable { .. } => match run_executable(&mut ctx) {
-                Ok(child) => children.push(child),
-                Err(e) => ctx.ewriteln(e)?,
-            },
-            _ if last_idx == 0 => {
-                run_builtin(&mut ctx);
-                return Ok(());
-            }
-            _ => {
-                let handle = thread::spawn(move || run_builtin(&mut ctx));
-                handles.push(handle);
+        if let Err(e) = Io::shell().and_then(|io| execute_commands(&list, &io)) {
+            if let Some(ExitRequest(code)) = e.downcast_ref() {
+                write_history_on_exit();
+                std::process::exit(*code);
             }
+            eprintln!("{}", e);
         }
     }
-
-    for handle in handles {
-        handle.join().unwrap();
-    }
-    for mut child in children {
-        child.wait().unwrap();
-    }
-
-    Ok(())
-}
-
-fn run_builtin(ctx: &mut CommandContext) {
-    if let Err(e) = handle_command(ctx) {
-        let _ = ctx.ewriteln(e);
-    }
-}
-
-fn run_executable(ctx: &mut CommandContext) -> Result<Child> {
-    let Command::Executable { name, .. } = &ctx.command else {
-        unreachable!("run_executable called with non-executable command");
-    };
-    let stdin = ctx
-        .piped_stdin
-        .take()
-        .map(Stdio::from)
-        .unwrap_or(Stdio::inherit());
-    let writer_ref = ctx.writer.borrow();
-    let writer = match &*writer_ref {
-        Writer::Pipe(p) => Stdio::from(p.try_clone()?),
-        Writer::File(f) => Stdio::from(f.try_clone()?),
-        Writer::Stdout(_) => Stdio::inherit(),
-    };
-    let stderr = ctx
-        .r_stderr
-        .clone()
-        .and_then(|(file, append)| create_file_writer(&file, append))
-        .map(Stdio::from)
-        .unwrap_or(Stdio::inherit());
-    let child = ProcessCommand::new(name)
-        .args(&ctx.args)
-        .stdin(stdin)
-        .stdout(writer)
-        .stderr(stderr)
-        .spawn();
-    child.context("Failed to execute command")
 }
@@ -0,0 +1,64 @@
+use anyhow::{bail, Result};
+use once_cell::sync::Lazy;
+use std::{collections::BTreeMap, sync::Mutex};
+
+/// Options toggled with `shopt`. All of them start out disabled.
+const SHOPT_OPTIONS: &[&str] = &["extglob", "failglob", "globstar", "nullglob"];
+
+static SHOPT: Lazy<Mutex<BTreeMap<&'static str, bool>>> =
+    Lazy::new(|| Mutex::new(SHOPT_OPTIONS.iter().map(|name| (*name, false)).collect()));
+
+pub fn shopt_enabled(name: &str) -> bool {
+    SHOPT.lock().unwrap().get(name).copied().unwrap_or(false)
+}
+
+pub fn set_shopt(name: &str, enabled: bool) -> Result<()> {
+    match SHOPT.lock().unwrap().get_mut(name) {
+        Some(value) => *value = enabled,
+        None => bail!("{}: invalid shell option name", name),
+    }
+    Ok(())
+}
+
+pub fn shopt_options() -> Vec<(&'static str, bool)> {
+    SHOPT
+        .lock()
+        .unwrap()
+        .iter()
+        .map(|(name, enabled)| (*name, *enabled))
+        .collect()
+}
+
+/// Options toggled with `set -o`, along with the flag that also toggles them
+/// as in `set -C`. All of them start out disabled.
+const SET_OPTIONS: &[(&str, Option<char>)] = &[("multios", None), ("noclobber", Some('C'))];
+
+static SET: Lazy<Mutex<BTreeMap<&'static str, bool>>> =
+    Lazy::new(|| Mutex::new(SET_OPTIONS.iter().map(|(name, _)| (*name, false)).collect()));
+
+pub fn option_enabled(name: &str) -> bool {
+    SET.lock().unwrap().get(name).copied().unwrap_or(false)
+}
+
+pub fn set_option(name: &str, enabled: bool) -> Result<()> {
+    match SET.lock().unwrap().get_mut(name) {
+        Some(value) => *value = enabled,
+        None => bail!("{}: invalid option name", name),
+    }
+    Ok(())
+}
+
+pub fn set_options() -> Vec<(&'static str, bool)> {
+    SET.lock()
+        .unwrap()
+        .iter()
+        .map(|(name, enabled)| (*name, *enabled))
+        .collect()
+}
+
+/// The option that a single-letter flag of `set` stands for.
+pub fn option_for_flag(flag: char) -> Option<&'static str> {
+    SET_OPTIONS
+        .iter()
+        .find_map(|(name, option_flag)| (*option_flag == Some(flag)).then_some(*name))
+}
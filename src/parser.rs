@@ -0,0 +1,724 @@
+use anyhow::{anyhow, bail, Result};
+use std::fmt::{self, Display};
+
+use crate::{
+    lexer::{needs_more_input, tokenize, HereDoc, Operator, Token},
+    vars::is_valid_name,
+};
+
+/// Commands separated by `;`, `&` or newlines, run one after another.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct List(pub Vec<AndOrList>);
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AndOrList {
+    pub first: Pipeline,
+    pub rest: Vec<(Connector, Pipeline)>,
+    /// Whether the list is followed by `&`, which runs it as a background
+    /// job.
+    pub background: bool,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Connector {
+    And,
+    Or,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Pipeline {
+    pub commands: Vec<CommandNode>,
+    /// Whether the pipeline starts with `!`, which inverts its status.
+    pub negated: bool,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum CommandNode {
+    Simple(SimpleCommand),
+    Compound(CompoundCommand, Vec<Redirect>),
+    Function(FunctionDef),
+}
+
+/// `name() compound-command`, which defines a function running the compound
+/// command, with its redirections, each time it is called.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FunctionDef {
+    pub name: String,
+    pub body: CompoundCommand,
+    pub redirects: Vec<Redirect>,
+}
+
+/// Words keep their original quoting; it is removed when the command runs.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct SimpleCommand {
+    pub assignments: Vec<Assignment>,
+    pub words: Vec<String>,
+    pub redirects: Vec<Redirect>,
+}
+
+/// A `NAME=value` word preceding the command name.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Assignment {
+    pub name: String,
+    pub value: String,
+}
+
+impl Assignment {
+    fn parse(word: &str) -> Option<Self> {
+        let (name, value) = word.split_once('=')?;
+        is_valid_name(name).then(|| Self {
+            name: name.to_string(),
+            value: value.to_string(),
+        })
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum CompoundCommand {
+    BraceGroup(List),
+    Subshell(List),
+    /// `((expr))`, which succeeds when the expression is non-zero.
+    Arith(String),
+    /// `if`, with the condition and body of the `if` and of each `elif`
+    /// in order, and the body of the `else`.
+    If {
+        branches: Vec<(List, List)>,
+        otherwise: Option<List>,
+    },
+    /// `while`, or `until` when `until` is set, which runs the body for as
+    /// long as the condition succeeds, or fails.
+    Loop {
+        condition: List,
+        body: List,
+        until: bool,
+    },
+    /// `for name [in word...]`, which runs the body with the variable set to
+    /// each word in turn, or to each positional parameter without `in`.
+    For {
+        name: String,
+        words: Option<Vec<String>>,
+        body: List,
+    },
+    /// `case word in pattern) list;; ... esac`, which runs the body of the
+    /// first item with a pattern matching the word.
+    Case {
+        word: String,
+        items: Vec<CaseItem>,
+    },
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CaseItem {
+    pub patterns: Vec<String>,
+    pub body: List,
+}
+
+/// Returned as an error when the input ends in the middle of a command, so
+/// that the caller can read more lines and try again.
+#[derive(Debug)]
+pub struct UnexpectedEof;
+
+impl Display for UnexpectedEof {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "syntax error: unexpected end of file")
+    }
+}
+
+impl std::error::Error for UnexpectedEof {}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Redirect {
+    pub fd: Option<u32>,
+    pub op: Operator,
+    pub target: String,
+    /// The document read by `<<` and `<<-`, whose target is the delimiter.
+    pub here_doc: Option<HereDoc>,
+}
+
+// Commands are displayed with their words as written, for job reports.
+
+impl Display for List {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, and_or) in self.0.iter().enumerate() {
+            if i > 0 {
+                write!(f, " ")?;
+            }
+            write!(f, "{}", and_or)?;
+            if and_or.background {
+                write!(f, " &")?;
+            } else if i + 1 < self.0.len() {
+                write!(f, ";")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Display for AndOrList {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.first)?;
+        for (connector, pipeline) in &self.rest {
+            let connector = match connector {
+                Connector::And => "&&",
+                Connector::Or => "||",
+            };
+            write!(f, " {} {}", connector, pipeline)?;
+        }
+        Ok(())
+    }
+}
+
+impl Display for Pipeline {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.negated {
+            write!(f, "! ")?;
+        }
+        for (i, command) in self.commands.iter().enumerate() {
+            if i > 0 {
+                write!(f, " | ")?;
+            }
+            write!(f, "{}", command)?;
+        }
+        Ok(())
+    }
+}
+
+impl Display for CommandNode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut parts: Vec<String> = vec![];
+        let redirects = match self {
+            CommandNode::Simple(cmd) => {
+                for assignment in &cmd.assignments {
+                    parts.push(format!("{}={}", assignment.name, assignment.value));
+                }
+                parts.extend(cmd.words.iter().cloned());
+                &cmd.redirects
+            }
+            CommandNode::Compound(compound, redirects) => {
+                parts.push(compound.to_string());
+                redirects
+            }
+            CommandNode::Function(def) => {
+                parts.push(format!("{}() {}", def.name, def.body));
+                &def.redirects
+            }
+        };
+        parts.extend(redirects.iter().map(Redirect::to_string));
+        write!(f, "{}", parts.join(" "))
+    }
+}
+
+impl Display for CompoundCommand {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CompoundCommand::BraceGroup(list) => write!(f, "{{ {} }}", terminated(list)),
+            CompoundCommand::Subshell(list) => write!(f, "({})", list),
+            CompoundCommand::Arith(expr) => write!(f, "(({}))", expr),
+            CompoundCommand::If {
+                branches,
+                otherwise,
+            } => {
+                for (i, (condition, body)) in branches.iter().enumerate() {
+                    let keyword = if i == 0 { "if" } else { "elif" };
+                    write!(
+                        f,
+                        "{} {} then {} ",
+                        keyword,
+                        terminated(condition),
+                        terminated(body)
+                    )?;
+                }
+                if let Some(body) = otherwise {
+                    write!(f, "else {} ", terminated(body))?;
+                }
+                write!(f, "fi")
+            }
+            CompoundCommand::Loop {
+                condition,
+                body,
+                until,
+            } => {
+                let keyword = if *until { "until" } else { "while" };
+                write!(
+                    f,
+                    "{} {} do {} done",
+                    keyword,
+                    terminated(condition),
+                    terminated(body)
+                )
+            }
+            CompoundCommand::For { name, words, body } => {
+                write!(f, "for {}", name)?;
+                if let Some(words) = words {
+                    write!(f, " in")?;
+                    for word in words {
+                        write!(f, " {}", word)?;
+                    }
+                }
+                write!(f, "; do {} done", terminated(body))
+            }
+            CompoundCommand::Case { word, items } => {
+                write!(f, "case {} in", word)?;
+                for item in items {
+                    write!(f, " {}) {};;", item.patterns.join("|"), item.body)?;
+                }
+                write!(f, " esac")
+            }
+        }
+    }
+}
+
+/// A list as written before a reserved word such as `then` or `}`: followed
+/// by `;` unless it already ends in `&`.
+fn terminated(list: &List) -> String {
+    match list.0.last() {
+        Some(last) if last.background => list.to_string(),
+        _ => format!("{};", list),
+    }
+}
+
+impl Display for Redirect {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(fd) = self.fd {
+            write!(f, "{}", fd)?;
+        }
+        write!(f, "{}{}", self.op.as_str(), self.target)
+    }
+}
+
+pub fn parse(input: &str) -> Result<List> {
+    let mut parser = Parser {
+        tokens: tokenize(input)?,
+        pos: 0,
+    };
+    let list = parser.parse_list()?;
+    if let Some(token) = parser.peek() {
+        bail!(unexpected(token));
+    }
+    Ok(list)
+}
+
+/// Whether the input ends in the middle of a command, such as inside an
+/// `if` or before the delimiter of a here-document, so that more lines have
+/// to be read before it can run.
+pub fn is_incomplete(input: &str) -> bool {
+    needs_more_input(input) || parse(input).is_err_and(|e| e.is::<UnexpectedEof>())
+}
+
+fn unexpected(token: &Token) -> String {
+    let text = match token {
+        Token::Word(word) => word.clone(),
+        Token::IoNumber(fd) => fd.to_string(),
+        Token::Arith(expr) => format!("(({}))", expr),
+        Token::HereDoc(delimiter, _) => delimiter.clone(),
+        Token::Op(op) => op.to_string(),
+    };
+    format!("syntax error near unexpected token `{}`", text)
+}
+
+/// The error for a token found where another was expected, or for the end of
+/// the input when there is none.
+fn unexpected_or_eof(token: Option<&Token>) -> anyhow::Error {
+    match token {
+        Some(token) => anyhow!(unexpected(token)),
+        None => UnexpectedEof.into(),
+    }
+}
+
+fn is_redirect_op(op: &Operator) -> bool {
+    matches!(
+        op,
+        Operator::Less
+            | Operator::Great
+            | Operator::DGreat
+            | Operator::Clobber
+            | Operator::LessGreat
+            | Operator::DLess
+            | Operator::DLessDash
+            | Operator::TLess
+            | Operator::LessAnd
+            | Operator::GreatAnd
+            | Operator::AndGreat
+            | Operator::AndDGreat
+    )
+}
+
+/// The reserved words that start a compound command.
+const COMPOUND_KEYWORDS: &[&str] = &["{", "if", "while", "until", "for", "case"];
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    fn peek_op(&self, op: Operator) -> bool {
+        self.peek() == Some(&Token::Op(op))
+    }
+
+    /// Reserved words such as `{` and `}` are only recognised where a command
+    /// could start, and only when they are written unquoted.
+    fn peek_reserved(&self, word: &str) -> bool {
+        matches!(self.peek(), Some(Token::Word(w)) if w == word)
+    }
+
+    fn skip_newlines(&mut self) {
+        while self.peek_op(Operator::Newline) {
+            self.pos += 1;
+        }
+    }
+
+    fn at_list_end(&self) -> bool {
+        matches!(
+            self.peek(),
+            None | Some(Token::Op(Operator::RParen | Operator::DSemi))
+        ) || ["}", "then", "elif", "else", "fi", "do", "done", "esac"]
+            .iter()
+            .any(|word| self.peek_reserved(word))
+    }
+
+    fn expect_reserved(&mut self, word: &str) -> Result<()> {
+        if self.peek_reserved(word) {
+            self.pos += 1;
+            return Ok(());
+        }
+        Err(unexpected_or_eof(self.peek()))
+    }
+
+    fn expect_word(&mut self) -> Result<String> {
+        match self.next() {
+            Some(Token::Word(word)) => Ok(word),
+            token => Err(unexpected_or_eof(token.as_ref())),
+        }
+    }
+
+    fn parse_list(&mut self) -> Result<List> {
+        let mut items = vec![];
+        loop {
+            self.skip_newlines();
+            if self.at_list_end() {
+                break;
+            }
+            let mut and_or = self.parse_and_or()?;
+            match self.peek() {
+                Some(Token::Op(Operator::Semi | Operator::Newline)) => self.pos += 1,
+                Some(Token::Op(Operator::Amp)) => {
+                    and_or.background = true;
+                    self.pos += 1;
+                }
+                _ => {
+                    items.push(and_or);
+                    break;
+                }
+            }
+            items.push(and_or);
+        }
+        Ok(List(items))
+    }
+
+    fn parse_and_or(&mut self) -> Result<AndOrList> {
+        let first = self.parse_pipeline()?;
+        let mut rest = vec![];
+        loop {
+            let connector = match self.peek() {
+                Some(Token::Op(Operator::AndIf)) => Connector::And,
+                Some(Token::Op(Operator::OrIf)) => Connector::Or,
+                _ => break,
+            };
+            self.pos += 1;
+            self.skip_newlines();
+            rest.push((connector, self.parse_pipeline()?));
+        }
+        Ok(AndOrList {
+            first,
+            rest,
+            background: false,
+        })
+    }
+
+    /// `a |& b` is parsed as `a 2>&1 | b`, with the redirection of standard
+    /// error applied after those of `a` itself.
+    fn parse_pipeline(&mut self) -> Result<Pipeline> {
+        let negated = self.peek_reserved("!");
+        if negated {
+            self.pos += 1;
+        }
+        let mut commands = vec![self.parse_command()?];
+        loop {
+            match self.peek() {
+                Some(Token::Op(Operator::Pipe)) => {}
+                Some(Token::Op(Operator::PipeAnd)) => {
+                    let stderr_to_stdout = Redirect {
+                        fd: Some(2),
+                        op: Operator::GreatAnd,
+                        target: "1".to_string(),
+                        here_doc: None,
+                    };
+                    match commands.last_mut() {
+                        Some(CommandNode::Simple(cmd)) => cmd.redirects.push(stderr_to_stdout),
+                        Some(CommandNode::Compound(_, redirects)) => {
+                            redirects.push(stderr_to_stdout)
+                        }
+                        // A definition writes nothing, and its redirections
+                        // belong to the function.
+                        Some(CommandNode::Function(_)) => {}
+                        None => unreachable!("pipeline without commands"),
+                    }
+                }
+                _ => break,
+            }
+            self.pos += 1;
+            self.skip_newlines();
+            commands.push(self.parse_command()?);
+        }
+        Ok(Pipeline { commands, negated })
+    }
+
+    fn parse_command(&mut self) -> Result<CommandNode> {
+        if let Some(name) = self.peek_function_name() {
+            self.pos += 3;
+            self.skip_newlines();
+            let Some(body) = self.parse_compound()? else {
+                return Err(unexpected_or_eof(self.peek()));
+            };
+            return Ok(CommandNode::Function(FunctionDef {
+                name,
+                body,
+                redirects: self.parse_redirects()?,
+            }));
+        }
+        match self.parse_compound()? {
+            Some(compound) => Ok(CommandNode::Compound(compound, self.parse_redirects()?)),
+            None => self.parse_simple_command().map(CommandNode::Simple),
+        }
+    }
+
+    /// The name of the function defined at this point, which is followed by
+    /// `()`.
+    fn peek_function_name(&self) -> Option<String> {
+        match (
+            self.peek(),
+            self.tokens.get(self.pos + 1),
+            self.tokens.get(self.pos + 2),
+        ) {
+            (
+                Some(Token::Word(name)),
+                Some(Token::Op(Operator::LParen)),
+                Some(Token::Op(Operator::RParen)),
+            ) if is_valid_name(name) => Some(name.clone()),
+            _ => None,
+        }
+    }
+
+    /// Parses the compound command starting here, if one does.
+    fn parse_compound(&mut self) -> Result<Option<CompoundCommand>> {
+        let keyword = match self.peek() {
+            Some(Token::Op(Operator::LParen)) => {
+                self.pos += 1;
+                let body = self.parse_group_body(")")?;
+                return Ok(Some(CompoundCommand::Subshell(body)));
+            }
+            Some(Token::Arith(expr)) => {
+                let expr = expr.clone();
+                self.pos += 1;
+                return Ok(Some(CompoundCommand::Arith(expr)));
+            }
+            Some(Token::Word(word)) if COMPOUND_KEYWORDS.contains(&word.as_str()) => word.clone(),
+            _ => return Ok(None),
+        };
+        self.pos += 1;
+        let compound = match keyword.as_str() {
+            "{" => CompoundCommand::BraceGroup(self.parse_group_body("}")?),
+            "if" => self.parse_if()?,
+            "for" => self.parse_for()?,
+            "case" => self.parse_case()?,
+            _ => CompoundCommand::Loop {
+                condition: self.parse_group_body("do")?,
+                body: self.parse_group_body("done")?,
+                until: keyword == "until",
+            },
+        };
+        Ok(Some(compound))
+    }
+
+    fn parse_redirects(&mut self) -> Result<Vec<Redirect>> {
+        let mut redirects = vec![];
+        while let Some(redirect) = self.parse_redirect()? {
+            redirects.push(redirect);
+        }
+        Ok(redirects)
+    }
+
+    fn parse_group_body(&mut self, close: &'static str) -> Result<List> {
+        self.parse_body(&[close]).map(|(body, _)| body)
+    }
+
+    /// Parses a non-empty list ended by one of `closers`, returning it with
+    /// the closer that ended it.
+    fn parse_body(&mut self, closers: &[&'static str]) -> Result<(List, &'static str)> {
+        let body = self.parse_list()?;
+        let token = self.next();
+        let text = match &token {
+            Some(Token::Word(w)) => w.as_str(),
+            Some(Token::Op(op)) => op.as_str(),
+            _ => "",
+        };
+        match closers.iter().find(|close| **close == text) {
+            Some(close) if !body.0.is_empty() => Ok((body, close)),
+            _ => Err(unexpected_or_eof(token.as_ref())),
+        }
+    }
+
+    fn parse_if(&mut self) -> Result<CompoundCommand> {
+        let mut branches = vec![];
+        loop {
+            let condition = self.parse_group_body("then")?;
+            let (body, close) = self.parse_body(&["elif", "else", "fi"])?;
+            branches.push((condition, body));
+            let otherwise = match close {
+                "elif" => continue,
+                "else" => Some(self.parse_group_body("fi")?),
+                _ => None,
+            };
+            return Ok(CompoundCommand::If {
+                branches,
+                otherwise,
+            });
+        }
+    }
+
+    fn parse_for(&mut self) -> Result<CompoundCommand> {
+        let name = self.expect_word()?;
+        if !is_valid_name(&name) {
+            bail!("`{}': not a valid identifier", name);
+        }
+        self.skip_newlines();
+        let words = if self.peek_reserved("in") {
+            self.pos += 1;
+            let mut words = vec![];
+            while let Some(Token::Word(word)) = self.peek() {
+                words.push(word.clone());
+                self.pos += 1;
+            }
+            match self.next() {
+                Some(Token::Op(Operator::Semi | Operator::Newline)) => {}
+                token => return Err(unexpected_or_eof(token.as_ref())),
+            }
+            Some(words)
+        } else {
+            if self.peek_op(Operator::Semi) {
+                self.pos += 1;
+            }
+            None
+        };
+        self.skip_newlines();
+        self.expect_reserved("do")?;
+        Ok(CompoundCommand::For {
+            name,
+            words,
+            body: self.parse_group_body("done")?,
+        })
+    }
+
+    /// Items may start with an optional `(`, and the last may leave out its
+    /// `;;`.
+    fn parse_case(&mut self) -> Result<CompoundCommand> {
+        let word = self.expect_word()?;
+        self.skip_newlines();
+        self.expect_reserved("in")?;
+        let mut items = vec![];
+        loop {
+            self.skip_newlines();
+            if self.peek_reserved("esac") {
+                self.pos += 1;
+                break;
+            }
+            if self.peek_op(Operator::LParen) {
+                self.pos += 1;
+            }
+            let mut patterns = vec![self.expect_word()?];
+            while self.peek_op(Operator::Pipe) {
+                self.pos += 1;
+                patterns.push(self.expect_word()?);
+            }
+            match self.next() {
+                Some(Token::Op(Operator::RParen)) => {}
+                token => return Err(unexpected_or_eof(token.as_ref())),
+            }
+            items.push(CaseItem {
+                patterns,
+                body: self.parse_list()?,
+            });
+            match self.next() {
+                Some(Token::Op(Operator::DSemi)) => {}
+                Some(Token::Word(w)) if w == "esac" => break,
+                token => return Err(unexpected_or_eof(token.as_ref())),
+            }
+        }
+        Ok(CompoundCommand::Case { word, items })
+    }
+
+    fn parse_simple_command(&mut self) -> Result<SimpleCommand> {
+        let mut command = SimpleCommand::default();
+        loop {
+            if let Some(redirect) = self.parse_redirect()? {
+                command.redirects.push(redirect);
+                continue;
+            }
+            match self.peek() {
+                Some(Token::Word(word)) => {
+                    match Assignment::parse(word) {
+                        Some(assignment) if command.words.is_empty() => {
+                            command.assignments.push(assignment)
+                        }
+                        _ => command.words.push(word.clone()),
+                    }
+                    self.pos += 1;
+                }
+                _ => break,
+            }
+        }
+        if command.assignments.is_empty()
+            && command.words.is_empty()
+            && command.redirects.is_empty()
+        {
+            return Err(unexpected_or_eof(self.peek()));
+        }
+        Ok(command)
+    }
+
+    fn parse_redirect(&mut self) -> Result<Option<Redirect>> {
+        let (fd, op) = match (self.peek(), self.tokens.get(self.pos + 1)) {
+            (Some(Token::IoNumber(fd)), Some(Token::Op(op))) => (Some(*fd), *op),
+            (Some(Token::Op(op)), _) if is_redirect_op(op) => (None, *op),
+            _ => return Ok(None),
+        };
+        self.pos += if fd.is_some() { 2 } else { 1 };
+        match self.next() {
+            Some(Token::Word(target)) => Ok(Some(Redirect {
+                fd,
+                op,
+                target,
+                here_doc: None,
+            })),
+            Some(Token::HereDoc(target, here_doc)) => Ok(Some(Redirect {
+                fd,
+                op,
+                target,
+                here_doc: Some(here_doc),
+            })),
+            Some(token) => bail!(unexpected(&token)),
+            None => bail!("syntax error near unexpected token `newline`"),
+        }
+    }
+}
@@ -0,0 +1,244 @@
+use crate::options::shopt_enabled;
+
+/// A shell pattern made of literal characters, `*`, `?`, bracket
+/// expressions and, with `shopt -s extglob`, extended patterns such as
+/// `+(a|b)`, as used by parameter and pathname expansion.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Pattern {
+    tokens: Vec<PatternToken>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum PatternToken {
+    Literal(char),
+    AnyChar,
+    AnyString,
+    Class {
+        negated: bool,
+        items: Vec<ClassItem>,
+    },
+    /// `?(..)`, `*(..)`, `+(..)`, `@(..)` or `!(..)`: zero or one, zero or
+    /// more, one or more, exactly one, or none of the `|`-separated
+    /// alternatives.
+    ExtGlob {
+        kind: char,
+        alternatives: Vec<Vec<PatternToken>>,
+    },
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum ClassItem {
+    Char(char),
+    Range(char, char),
+    Named(String),
+}
+
+impl Pattern {
+    /// Builds a pattern from expanded characters; quoted characters always
+    /// match themselves.
+    pub fn new(chars: &[(char, bool)]) -> Self {
+        Self {
+            tokens: parse_tokens(chars),
+        }
+    }
+
+    /// Whether any character of the pattern is special, i.e. whether matching
+    /// can differ from a plain string comparison.
+    pub fn has_wildcards(&self) -> bool {
+        self.tokens
+            .iter()
+            .any(|token| !matches!(token, PatternToken::Literal(_)))
+    }
+
+    pub fn matches(&self, text: &str) -> bool {
+        let text: Vec<char> = text.chars().collect();
+        self.matches_chars(&text)
+    }
+
+    pub fn matches_chars(&self, text: &[char]) -> bool {
+        match_tokens(&self.tokens, text)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tokens.is_empty()
+    }
+}
+
+fn parse_tokens(chars: &[(char, bool)]) -> Vec<PatternToken> {
+    let mut tokens = vec![];
+    let mut pos = 0;
+    while let Some(&(c, quoted)) = chars.get(pos) {
+        pos += 1;
+        if matches!(c, '?' | '*' | '+' | '@' | '!')
+            && !quoted
+            && chars.get(pos) == Some(&('(', false))
+            && shopt_enabled("extglob")
+        {
+            if let Some(len) = group_len(&chars[pos + 1..]) {
+                let alternatives = split_alternatives(&chars[pos + 1..pos + 1 + len])
+                    .into_iter()
+                    .map(parse_tokens)
+                    .collect();
+                tokens.push(PatternToken::ExtGlob {
+                    kind: c,
+                    alternatives,
+                });
+                pos += len + 2;
+                continue;
+            }
+        }
+        let token = match c {
+            _ if quoted => PatternToken::Literal(c),
+            '*' => PatternToken::AnyString,
+            '?' => PatternToken::AnyChar,
+            '[' => match parse_class(&chars[pos..]) {
+                Some((token, len)) => {
+                    pos += len;
+                    token
+                }
+                None => PatternToken::Literal(c),
+            },
+            c => PatternToken::Literal(c),
+        };
+        tokens.push(token);
+    }
+    tokens
+}
+
+/// Length of the body of an extended pattern, up to the unquoted `)` that
+/// closes it, or `None` if it is never closed.
+fn group_len(chars: &[(char, bool)]) -> Option<usize> {
+    let mut depth = 0;
+    for (i, &(c, quoted)) in chars.iter().enumerate() {
+        match c {
+            _ if quoted => {}
+            '(' => depth += 1,
+            ')' if depth == 0 => return Some(i),
+            ')' => depth -= 1,
+            _ => {}
+        }
+    }
+    None
+}
+
+fn split_alternatives(chars: &[(char, bool)]) -> Vec<&[(char, bool)]> {
+    let mut alternatives = vec![];
+    let (mut depth, mut start) = (0, 0);
+    for (i, &(c, quoted)) in chars.iter().enumerate() {
+        match c {
+            _ if quoted => {}
+            '(' => depth += 1,
+            ')' => depth -= 1,
+            '|' if depth == 0 => {
+                alternatives.push(&chars[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    alternatives.push(&chars[start..]);
+    alternatives
+}
+
+fn match_tokens(tokens: &[PatternToken], text: &[char]) -> bool {
+    let Some((token, rest)) = tokens.split_first() else {
+        return text.is_empty();
+    };
+    match token {
+        PatternToken::AnyString => (0..=text.len()).any(|i| match_tokens(rest, &text[i..])),
+        PatternToken::ExtGlob { kind, alternatives } => (0..=text.len()).any(|i| {
+            matches_ext(*kind, alternatives, &text[..i]) && match_tokens(rest, &text[i..])
+        }),
+        token => match text.split_first() {
+            Some((c, text)) => matches_char(token, *c) && match_tokens(rest, text),
+            None => false,
+        },
+    }
+}
+
+fn matches_ext(kind: char, alternatives: &[Vec<PatternToken>], text: &[char]) -> bool {
+    let any = |text: &[char]| alternatives.iter().any(|alt| match_tokens(alt, text));
+    match kind {
+        '?' => text.is_empty() || any(text),
+        '@' => any(text),
+        '!' => !any(text),
+        '*' => text.is_empty() || matches_ext('+', alternatives, text),
+        _ => {
+            any(text)
+                || (1..text.len())
+                    .any(|i| any(&text[..i]) && matches_ext('+', alternatives, &text[i..]))
+        }
+    }
+}
+
+fn matches_char(token: &PatternToken, c: char) -> bool {
+    match token {
+        PatternToken::Literal(l) => *l == c,
+        PatternToken::AnyChar => true,
+        PatternToken::AnyString | PatternToken::ExtGlob { .. } => {
+            unreachable!("variable-length tokens are handled by match_tokens")
+        }
+        PatternToken::Class { negated, items } => {
+            items.iter().any(|item| match item {
+                ClassItem::Char(i) => *i == c,
+                ClassItem::Range(lo, hi) => (*lo..=*hi).contains(&c),
+                ClassItem::Named(name) => matches_named_class(name, c),
+            }) != *negated
+        }
+    }
+}
+
+fn matches_named_class(name: &str, c: char) -> bool {
+    match name {
+        "alnum" => c.is_alphanumeric(),
+        "alpha" => c.is_alphabetic(),
+        "blank" => c == ' ' || c == '\t',
+        "cntrl" => c.is_control(),
+        "digit" => c.is_ascii_digit(),
+        "graph" => c.is_ascii_graphic(),
+        "lower" => c.is_lowercase(),
+        "print" => c.is_ascii_graphic() || c == ' ',
+        "punct" => c.is_ascii_punctuation(),
+        "space" => c.is_whitespace(),
+        "upper" => c.is_uppercase(),
+        "xdigit" => c.is_ascii_hexdigit(),
+        _ => false,
+    }
+}
+
+/// Parses a bracket expression following its opening `[`. Returns the token
+/// and the number of characters consumed, or `None` if the bracket is not
+/// closed, in which case it matches a literal `[`.
+fn parse_class(chars: &[(char, bool)]) -> Option<(PatternToken, usize)> {
+    let mut pos = 0;
+    let negated = matches!(chars.first(), Some(('!' | '^', false)));
+    if negated {
+        pos += 1;
+    }
+    let mut items = vec![];
+    loop {
+        let &(c, quoted) = chars.get(pos)?;
+        // A `]` right after the opening bracket is taken literally.
+        if c == ']' && !quoted && (pos > usize::from(negated)) {
+            return Some((PatternToken::Class { negated, items }, pos + 1));
+        }
+        if c == '[' && !quoted && chars.get(pos + 1) == Some(&(':', false)) {
+            let rest: String = chars[pos + 2..].iter().map(|(c, _)| c).collect();
+            if let Some(end) = rest.find(":]") {
+                items.push(ClassItem::Named(rest[..end].to_string()));
+                pos += 2 + rest[..end].chars().count() + 2;
+                continue;
+            }
+        }
+        match (chars.get(pos + 1), chars.get(pos + 2)) {
+            (Some(('-', false)), Some(&(hi, hi_quoted))) if hi != ']' || hi_quoted => {
+                items.push(ClassItem::Range(c, hi));
+                pos += 3;
+            }
+            _ => {
+                items.push(ClassItem::Char(c));
+                pos += 1;
+            }
+        }
+    }
+}
@@ -0,0 +1,155 @@
+use anyhow::{bail, Result};
+use once_cell::sync::Lazy;
+use std::{
+    collections::BTreeMap,
+    sync::{
+        atomic::{AtomicI32, Ordering},
+        Mutex,
+    },
+};
+
+static LAST_STATUS: AtomicI32 = AtomicI32::new(0);
+
+/// The arguments of the function being run, exposed as `$1`, `$2`, ... and
+/// counted by `$#`. Empty at the top level.
+static POSITIONAL_PARAMS: Lazy<Mutex<Vec<String>>> = Lazy::new(|| Mutex::new(vec![]));
+
+/// Exit status of the most recently completed pipeline, exposed as `$?`.
+pub fn last_status() -> i32 {
+    LAST_STATUS.load(Ordering::Relaxed)
+}
+
+pub fn set_last_status(status: i32) {
+    LAST_STATUS.store(status, Ordering::Relaxed);
+}
+
+pub fn positional_params() -> Vec<String> {
+    POSITIONAL_PARAMS.lock().unwrap().clone()
+}
+
+/// Replaces the positional parameters, returning the previous ones so that
+/// they can be put back when a function returns.
+pub fn set_positional_params(params: Vec<String>) -> Vec<String> {
+    std::mem::replace(&mut *POSITIONAL_PARAMS.lock().unwrap(), params)
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Variable {
+    pub value: String,
+    pub exported: bool,
+    pub readonly: bool,
+}
+
+/// Shell variables, kept apart from the process environment. Only exported
+/// variables are passed on to executables.
+#[derive(Clone, Debug)]
+pub struct ShellVars {
+    vars: BTreeMap<String, Variable>,
+}
+
+impl Default for ShellVars {
+    fn default() -> Self {
+        let vars = std::env::vars()
+            .map(|(name, value)| {
+                let var = Variable {
+                    value,
+                    exported: true,
+                    readonly: false,
+                };
+                (name, var)
+            })
+            .collect();
+        Self { vars }
+    }
+}
+
+static SHELL_VARS: Lazy<Mutex<ShellVars>> = Lazy::new(|| Mutex::new(ShellVars::default()));
+
+pub fn get_var(name: &str) -> Option<String> {
+    let vars = SHELL_VARS.lock().unwrap();
+    vars.vars.get(name).map(|var| var.value.clone())
+}
+
+pub fn set_var(name: &str, value: &str) -> Result<()> {
+    let mut vars = SHELL_VARS.lock().unwrap();
+    let var = vars.vars.entry(name.to_string()).or_default();
+    if var.readonly {
+        bail!("{}: readonly variable", name);
+    }
+    var.value = value.to_string();
+    Ok(())
+}
+
+pub fn unset_var(name: &str) -> Result<()> {
+    let mut vars = SHELL_VARS.lock().unwrap();
+    if vars.vars.get(name).is_some_and(|var| var.readonly) {
+        bail!("{}: cannot unset: readonly variable", name);
+    }
+    vars.vars.remove(name);
+    Ok(())
+}
+
+pub fn export_var(name: &str, value: Option<&str>) -> Result<()> {
+    if let Some(value) = value {
+        set_var(name, value)?;
+    }
+    let mut vars = SHELL_VARS.lock().unwrap();
+    vars.vars.entry(name.to_string()).or_default().exported = true;
+    Ok(())
+}
+
+pub fn set_readonly(name: &str, value: Option<&str>) -> Result<()> {
+    if let Some(value) = value {
+        set_var(name, value)?;
+    }
+    let mut vars = SHELL_VARS.lock().unwrap();
+    vars.vars.entry(name.to_string()).or_default().readonly = true;
+    Ok(())
+}
+
+/// All variables sorted by name, as listed by `export -p` and `readonly -p`.
+pub fn all_vars() -> Vec<(String, Variable)> {
+    let vars = SHELL_VARS.lock().unwrap();
+    vars.vars
+        .iter()
+        .map(|(name, var)| (name.clone(), var.clone()))
+        .collect()
+}
+
+/// The environment handed to executables.
+pub fn exported_env() -> Vec<(String, String)> {
+    let vars = SHELL_VARS.lock().unwrap();
+    vars.vars
+        .iter()
+        .filter(|(_, var)| var.exported)
+        .map(|(name, var)| (name.clone(), var.value.clone()))
+        .collect()
+}
+
+/// Takes a copy of one variable, or `None` if it is unset, so that a
+/// temporary assignment to it can be undone with `restore_var`.
+pub fn save_var(name: &str) -> Option<Variable> {
+    let vars = SHELL_VARS.lock().unwrap();
+    vars.vars.get(name).cloned()
+}
+
+pub fn restore_var(name: &str, saved: Option<Variable>) {
+    let mut vars = SHELL_VARS.lock().unwrap();
+    match saved {
+        Some(var) => vars.vars.insert(name.to_string(), var),
+        None => vars.vars.remove(name),
+    };
+}
+
+pub fn is_name_start(c: char) -> bool {
+    c == '_' || c.is_ascii_alphabetic()
+}
+
+pub fn is_name_char(c: char) -> bool {
+    c == '_' || c.is_ascii_alphanumeric()
+}
+
+pub fn is_valid_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars.next().is_some_and(is_name_start) && chars.all(is_name_char)
+}
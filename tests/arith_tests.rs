@@ -0,0 +1,94 @@
+#[cfg(test)]
+mod tests {
+    use codecrafters_shell::arith::evaluate;
+    use codecrafters_shell::vars::{get_var, set_var};
+
+    fn eval(expr: &str) -> i64 {
+        evaluate(expr).unwrap()
+    }
+
+    #[test]
+    fn test_precedence() {
+        assert_eq!(eval("1 + 2 * 3"), 7);
+        assert_eq!(eval("(1 + 2) * 3"), 9);
+        assert_eq!(eval("2 ** 3 ** 2"), 512);
+        assert_eq!(eval("-2 ** 2"), 4);
+        assert_eq!(eval("1 + 2 << 1"), 6);
+        assert_eq!(eval("1 | 2 ^ 3 & 4"), 3);
+    }
+
+    #[test]
+    fn test_division_truncates() {
+        assert_eq!(eval("7 / 2"), 3);
+        assert_eq!(eval("-7 / 2"), -3);
+        assert_eq!(eval("-7 % 3"), -1);
+    }
+
+    #[test]
+    fn test_comparison_and_logic() {
+        assert_eq!(eval("3 > 2 && 2 >= 2"), 1);
+        assert_eq!(eval("1 == 2 || !5"), 0);
+        assert_eq!(eval("~0"), -1);
+    }
+
+    #[test]
+    fn test_ternary() {
+        assert_eq!(eval("1 ? 2 : 3"), 2);
+        assert_eq!(eval("0 ? 2 : 0 ? 3 : 4"), 4);
+    }
+
+    #[test]
+    fn test_number_bases() {
+        assert_eq!(eval("0x1f"), 31);
+        assert_eq!(eval("017"), 15);
+        assert_eq!(eval("2#101"), 5);
+        assert_eq!(eval("36#z"), 35);
+        assert!(evaluate("09").is_err());
+    }
+
+    #[test]
+    fn test_variables() {
+        set_var("ARITH_TEST_A", "6").unwrap();
+        set_var("ARITH_TEST_EXPR", "ARITH_TEST_A * 2").unwrap();
+        assert_eq!(eval("ARITH_TEST_A + 1"), 7);
+        assert_eq!(eval("ARITH_TEST_EXPR + 1"), 13);
+        assert_eq!(eval("ARITH_TEST_UNSET + 1"), 1);
+    }
+
+    #[test]
+    fn test_assignment() {
+        assert_eq!(eval("ARITH_TEST_B = 2, ARITH_TEST_B *= 5"), 10);
+        assert_eq!(get_var("ARITH_TEST_B").as_deref(), Some("10"));
+        assert_eq!(eval("ARITH_TEST_B++ + ARITH_TEST_B"), 21);
+        assert_eq!(eval("--ARITH_TEST_B"), 10);
+        assert_eq!(eval("ARITH_TEST_B <<= 1"), 20);
+    }
+
+    #[test]
+    fn test_short_circuit_skips_side_effects() {
+        assert_eq!(eval("0 && (ARITH_TEST_C = 1)"), 0);
+        assert_eq!(eval("1 ? 2 : (ARITH_TEST_C = 1)"), 2);
+        assert_eq!(get_var("ARITH_TEST_C"), None);
+    }
+
+    #[test]
+    fn test_division_by_zero() {
+        let err = evaluate("1 / 0").unwrap_err();
+        assert_eq!(err.to_string(), "1 / 0: division by 0");
+        assert!(evaluate("1 % 0").is_err());
+    }
+
+    #[test]
+    fn test_syntax_errors() {
+        assert!(evaluate("1 +").is_err());
+        assert!(evaluate("(1").is_err());
+        assert!(evaluate("1 2").is_err());
+        assert!(evaluate("1 ? 2").is_err());
+    }
+
+    #[test]
+    fn test_empty_expression() {
+        assert_eq!(eval(""), 0);
+        assert_eq!(eval("  "), 0);
+    }
+}
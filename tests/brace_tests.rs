@@ -0,0 +1,89 @@
+#[cfg(test)]
+mod tests {
+    use codecrafters_shell::brace::expand_braces;
+
+    fn braces(word: &str) -> Vec<String> {
+        expand_braces(word).unwrap()
+    }
+
+    #[test]
+    fn test_no_braces() {
+        assert_eq!(braces("abc"), vec!["abc"]);
+    }
+
+    #[test]
+    fn test_comma_list() {
+        assert_eq!(braces("src/{bin,lib}"), vec!["src/bin", "src/lib"]);
+        assert_eq!(braces("a{,b}"), vec!["a", "ab"]);
+    }
+
+    #[test]
+    fn test_cartesian_product() {
+        assert_eq!(braces("{a,b}{1,2}"), vec!["a1", "a2", "b1", "b2"]);
+    }
+
+    #[test]
+    fn test_nested() {
+        assert_eq!(braces("{a,b{1,2}}x"), vec!["ax", "b1x", "b2x"]);
+    }
+
+    #[test]
+    fn test_numeric_sequence() {
+        assert_eq!(
+            braces("file{1..3}.txt"),
+            vec!["file1.txt", "file2.txt", "file3.txt"]
+        );
+        assert_eq!(braces("{3..1}"), vec!["3", "2", "1"]);
+        assert_eq!(braces("{0..10..5}"), vec!["0", "5", "10"]);
+        assert_eq!(braces("{-1..1}"), vec!["-1", "0", "1"]);
+    }
+
+    #[test]
+    fn test_zero_padding() {
+        assert_eq!(braces("{08..11}"), vec!["08", "09", "10", "11"]);
+        assert_eq!(braces("{1..010..4}"), vec!["001", "005", "009"]);
+    }
+
+    #[test]
+    fn test_character_sequence() {
+        assert_eq!(braces("{a..e..2}"), vec!["a", "c", "e"]);
+        assert_eq!(braces("{C..A}"), vec!["C", "B", "A"]);
+    }
+
+    #[test]
+    fn test_invalid_braces_are_literal() {
+        assert_eq!(braces("{a}"), vec!["{a}"]);
+        assert_eq!(braces("{}"), vec!["{}"]);
+        assert_eq!(braces("{a,b"), vec!["{a,b"]);
+        assert_eq!(braces("{1..a}"), vec!["{1..a}"]);
+        assert_eq!(braces("{a}{b,c}"), vec!["{a}b", "{a}c"]);
+    }
+
+    #[test]
+    fn test_quoting_is_respected() {
+        assert_eq!(braces("'{a,b}'"), vec!["'{a,b}'"]);
+        assert_eq!(braces(r"\{a,b}"), vec![r"\{a,b}"]);
+        assert_eq!(braces(r#"{"a,b",c}"#), vec![r#""a,b""#, "c"]);
+        assert_eq!(braces("${x}{1,2}"), vec!["${x}1", "${x}2"]);
+        assert_eq!(braces("${x:-{a,b}}"), vec!["${x:-{a,b}}"]);
+    }
+
+    #[test]
+    fn test_process_substitution_is_not_expanded() {
+        assert_eq!(braces("<(echo {a,b})"), vec!["<(echo {a,b})"]);
+    }
+
+    #[test]
+    fn test_huge_sequences_are_rejected() {
+        assert!(expand_braces("{-9223372036854775808..9223372036854775807}").is_err());
+        assert!(expand_braces("{1..2000000}").is_err());
+        assert_eq!(
+            braces("{-9223372036854775808..9223372036854775807..9223372036854775807}"),
+            vec!["-9223372036854775808", "-1", "9223372036854775806"]
+        );
+        assert_eq!(
+            braces("{9223372036854775807..9223372036854775806}"),
+            vec!["9223372036854775807", "9223372036854775806"]
+        );
+    }
+}
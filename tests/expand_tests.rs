@@ -0,0 +1,252 @@
+#[cfg(test)]
+mod tests {
+    use codecrafters_shell::expand::{expand_assignment, expand_single, expand_word};
+    use codecrafters_shell::vars::set_var;
+
+    fn fields(word: &str) -> Vec<String> {
+        expand_word(word).unwrap()
+    }
+
+    fn set(name: &str, value: &str) {
+        set_var(name, value).unwrap();
+    }
+
+    #[test]
+    fn test_plain_word() {
+        assert_eq!(fields("foo"), vec!["foo".to_string()]);
+    }
+
+    #[test]
+    fn test_variable() {
+        set("EXPAND_TEST_VAR", "value");
+        assert_eq!(fields("$EXPAND_TEST_VAR"), vec!["value".to_string()]);
+        assert_eq!(fields("${EXPAND_TEST_VAR}"), vec!["value".to_string()]);
+    }
+
+    #[test]
+    fn test_variable_adjacent_text() {
+        set("EXPAND_TEST_ADJ", "mid");
+        assert_eq!(
+            fields("a${EXPAND_TEST_ADJ}b/$EXPAND_TEST_ADJ.c"),
+            vec!["amidb/mid.c".to_string()]
+        );
+    }
+
+    #[test]
+    fn test_unset_variable_is_empty() {
+        assert_eq!(fields("$EXPAND_TEST_UNSET"), Vec::<String>::new());
+        assert_eq!(fields(r#""$EXPAND_TEST_UNSET""#), vec![String::new()]);
+        assert_eq!(fields("a${EXPAND_TEST_UNSET}b"), vec!["ab".to_string()]);
+    }
+
+    #[test]
+    fn test_single_quotes_prevent_expansion() {
+        set("EXPAND_TEST_SQ", "value");
+        assert_eq!(
+            fields("'$EXPAND_TEST_SQ'"),
+            vec!["$EXPAND_TEST_SQ".to_string()]
+        );
+    }
+
+    #[test]
+    fn test_double_quotes_allow_expansion() {
+        set("EXPAND_TEST_DQ", "a  b");
+        assert_eq!(fields(r#""<$EXPAND_TEST_DQ>""#), vec!["<a  b>".to_string()]);
+    }
+
+    #[test]
+    fn test_escaped_dollar() {
+        set("EXPAND_TEST_ESC", "value");
+        assert_eq!(
+            fields(r"\$EXPAND_TEST_ESC"),
+            vec!["$EXPAND_TEST_ESC".to_string()]
+        );
+        assert_eq!(
+            fields(r#""\$EXPAND_TEST_ESC""#),
+            vec!["$EXPAND_TEST_ESC".to_string()]
+        );
+    }
+
+    #[test]
+    fn test_unquoted_expansion_is_split() {
+        set("EXPAND_TEST_SPLIT", " one  two ");
+        assert_eq!(
+            fields("x$EXPAND_TEST_SPLIT"),
+            vec!["x".to_string(), "one".to_string(), "two".to_string()]
+        );
+    }
+
+    #[test]
+    fn test_lone_dollar_is_literal() {
+        assert_eq!(fields("$"), vec!["$".to_string()]);
+        assert_eq!(fields("a$-b"), vec!["a$-b".to_string()]);
+    }
+
+    #[test]
+    fn test_expand_single_does_not_split() {
+        set("EXPAND_TEST_SINGLE", "a b");
+        assert_eq!(expand_single("$EXPAND_TEST_SINGLE").unwrap(), "a b");
+    }
+
+    #[test]
+    fn test_bad_substitution() {
+        assert!(expand_word("${a b}").is_err());
+        assert!(expand_word("${}").is_err());
+    }
+
+    fn expand(word: &str) -> String {
+        expand_single(word).unwrap()
+    }
+
+    #[test]
+    fn test_default_value() {
+        set("EXPAND_TEST_DEF_EMPTY", "");
+        set("EXPAND_TEST_DEF_SET", "set");
+        assert_eq!(expand("${EXPAND_TEST_DEF_UNSET:-dflt}"), "dflt");
+        assert_eq!(expand("${EXPAND_TEST_DEF_EMPTY:-dflt}"), "dflt");
+        assert_eq!(expand("${EXPAND_TEST_DEF_EMPTY-dflt}"), "");
+        assert_eq!(expand("${EXPAND_TEST_DEF_SET:-dflt}"), "set");
+    }
+
+    #[test]
+    fn test_default_value_is_expanded() {
+        set("EXPAND_TEST_DEF_INNER", "inner");
+        assert_eq!(
+            expand("${EXPAND_TEST_DEF_NONE:-$EXPAND_TEST_DEF_INNER/x}"),
+            "inner/x"
+        );
+    }
+
+    #[test]
+    fn test_quoted_default_value_is_not_split() {
+        assert_eq!(
+            fields(r#"${EXPAND_TEST_DEF_Q:-"a b"}"#),
+            vec!["a b".to_string()]
+        );
+        assert_eq!(
+            fields("${EXPAND_TEST_DEF_Q:-a b}"),
+            vec!["a".to_string(), "b".to_string()]
+        );
+    }
+
+    #[test]
+    fn test_assign_default() {
+        assert_eq!(expand("${EXPAND_TEST_ASSIGN:=first}"), "first");
+        assert_eq!(expand("${EXPAND_TEST_ASSIGN:=second}"), "first");
+        assert_eq!(expand("$EXPAND_TEST_ASSIGN"), "first");
+    }
+
+    #[test]
+    fn test_error_if_unset() {
+        let err = expand_single("${EXPAND_TEST_ERR_UNSET:?is required}").unwrap_err();
+        assert_eq!(err.to_string(), "EXPAND_TEST_ERR_UNSET: is required");
+        let err = expand_single("${EXPAND_TEST_ERR_UNSET:?}").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "EXPAND_TEST_ERR_UNSET: parameter null or not set"
+        );
+    }
+
+    #[test]
+    fn test_alternative_value() {
+        set("EXPAND_TEST_ALT", "x");
+        set("EXPAND_TEST_ALT_EMPTY", "");
+        assert_eq!(expand("${EXPAND_TEST_ALT:+alt}"), "alt");
+        assert_eq!(expand("${EXPAND_TEST_ALT_EMPTY:+alt}"), "");
+        assert_eq!(expand("${EXPAND_TEST_ALT_EMPTY+alt}"), "alt");
+        assert_eq!(expand("${EXPAND_TEST_ALT_UNSET+alt}"), "");
+    }
+
+    #[test]
+    fn test_length() {
+        set("EXPAND_TEST_LEN", "héllo");
+        assert_eq!(expand("${#EXPAND_TEST_LEN}"), "5");
+        assert_eq!(expand("${#EXPAND_TEST_LEN_UNSET}"), "0");
+    }
+
+    #[test]
+    fn test_remove_prefix() {
+        set("EXPAND_TEST_PATH", "/usr/local/lib/file.tar.gz");
+        assert_eq!(
+            expand("${EXPAND_TEST_PATH#*/}"),
+            "usr/local/lib/file.tar.gz"
+        );
+        assert_eq!(expand("${EXPAND_TEST_PATH##*/}"), "file.tar.gz");
+    }
+
+    #[test]
+    fn test_remove_suffix() {
+        set("EXPAND_TEST_FILE", "file.tar.gz");
+        assert_eq!(expand("${EXPAND_TEST_FILE%.*}"), "file.tar");
+        assert_eq!(expand("${EXPAND_TEST_FILE%%.*}"), "file");
+        assert_eq!(expand("${EXPAND_TEST_FILE%.zip}"), "file.tar.gz");
+    }
+
+    #[test]
+    fn test_quoted_pattern_is_literal() {
+        set("EXPAND_TEST_STAR", "a*b*c");
+        assert_eq!(expand(r#"${EXPAND_TEST_STAR#"a*"}"#), "b*c");
+        assert_eq!(expand(r"${EXPAND_TEST_STAR%\**}"), "a*b");
+    }
+
+    #[test]
+    fn test_substitution() {
+        set("EXPAND_TEST_SUB", "foo bar foo");
+        assert_eq!(expand("${EXPAND_TEST_SUB/foo/baz}"), "baz bar foo");
+        assert_eq!(expand("${EXPAND_TEST_SUB//foo/baz}"), "baz bar baz");
+        assert_eq!(expand("${EXPAND_TEST_SUB/#foo/X}"), "X bar foo");
+        assert_eq!(expand("${EXPAND_TEST_SUB/%foo/X}"), "foo bar X");
+        assert_eq!(expand("${EXPAND_TEST_SUB/#bar/X}"), "foo bar foo");
+        assert_eq!(expand("${EXPAND_TEST_SUB// /}"), "foobarfoo");
+        assert_eq!(expand("${EXPAND_TEST_SUB/o*/-}"), "f-");
+    }
+
+    #[test]
+    fn test_substring() {
+        set("EXPAND_TEST_SUBSTR", "abcdef");
+        assert_eq!(expand("${EXPAND_TEST_SUBSTR:2}"), "cdef");
+        assert_eq!(expand("${EXPAND_TEST_SUBSTR:1:3}"), "bcd");
+        assert_eq!(expand("${EXPAND_TEST_SUBSTR: -2}"), "ef");
+        assert_eq!(expand("${EXPAND_TEST_SUBSTR:1:-2}"), "bcd");
+        assert_eq!(expand("${EXPAND_TEST_SUBSTR:10}"), "");
+        assert_eq!(
+            expand("${EXPAND_TEST_SUBSTR:1:9223372036854775807}"),
+            "bcdef"
+        );
+        assert!(expand_single("${EXPAND_TEST_SUBSTR:4:-3}").is_err());
+    }
+
+    // Only this test changes HOME, PWD and OLDPWD in the test process.
+    #[test]
+    fn test_tilde() {
+        set("HOME", "/home/user");
+        set("PWD", "/cur");
+        set("OLDPWD", "/old");
+        assert_eq!(fields("~"), vec!["/home/user"]);
+        assert_eq!(fields("~/src"), vec!["/home/user/src"]);
+        assert_eq!(fields("~+/x"), vec!["/cur/x"]);
+        assert_eq!(fields("~-"), vec!["/old"]);
+        assert_eq!(fields("a~ '~' \\~ \"~\"/x"), vec!["a~", "~", "~", "~/x"]);
+        assert_eq!(fields("~no_such_user_here/x"), vec!["~no_such_user_here/x"]);
+        assert_eq!(expand_single("~").unwrap(), "/home/user".to_string());
+        set("HOME", "/home/with space");
+        assert_eq!(fields("~"), vec!["/home/with space"]);
+    }
+
+    #[test]
+    fn test_tilde_user() {
+        let root = expand_single("~root").unwrap();
+        assert!(!root.starts_with('~'));
+        assert_eq!(expand_single("~root/x").unwrap(), format!("{root}/x"));
+    }
+
+    #[test]
+    fn test_tilde_in_assignment() {
+        let home = expand_single("~root").unwrap();
+        assert_eq!(
+            expand_assignment("~root/bin:~root:x~:'~root'").unwrap(),
+            format!("{home}/bin:{home}:x~:~root")
+        );
+        assert_eq!(expand_single("a:~root").unwrap(), "a:~root".to_string());
+    }
+}
@@ -0,0 +1,97 @@
+#[cfg(test)]
+mod tests {
+    use codecrafters_shell::{glob::glob, options::set_shopt};
+    use std::fs;
+    use std::path::{Path, PathBuf};
+
+    fn setup(name: &str, files: &[&str]) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("glob-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        for file in files {
+            let path = dir.join(file);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, "").unwrap();
+        }
+        dir
+    }
+
+    fn unquoted(s: &str) -> Vec<(char, bool)> {
+        s.chars().map(|c| (c, false)).collect()
+    }
+
+    fn matches(dir: &Path, pattern: &str) -> Vec<String> {
+        set_shopt("extglob", true).unwrap();
+        set_shopt("globstar", true).unwrap();
+        let prefix = format!("{}/", dir.display());
+        glob(&unquoted(&format!("{}{}", prefix, pattern)))
+            .into_iter()
+            .map(|path| path.strip_prefix(&prefix).unwrap().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn test_sorted_matches() {
+        let dir = setup("sorted", &["c.rs", "a.rs", "b.txt", "b.rs"]);
+        assert_eq!(matches(&dir, "*.rs"), vec!["a.rs", "b.rs", "c.rs"]);
+        assert_eq!(matches(&dir, "?.txt"), vec!["b.txt"]);
+        assert_eq!(matches(&dir, "[ab].*"), vec!["a.rs", "b.rs", "b.txt"]);
+    }
+
+    #[test]
+    fn test_hidden_files() {
+        let dir = setup("hidden", &[".env", "visible"]);
+        assert_eq!(matches(&dir, "*"), vec!["visible"]);
+        assert_eq!(matches(&dir, ".*"), vec![".env"]);
+    }
+
+    #[test]
+    fn test_directories() {
+        let dir = setup("dirs", &["a/x.rs", "b/y.rs", "b/z.txt", "file"]);
+        assert_eq!(matches(&dir, "*/*.rs"), vec!["a/x.rs", "b/y.rs"]);
+        assert_eq!(matches(&dir, "*/"), vec!["a/", "b/"]);
+        assert_eq!(matches(&dir, "*/z.txt"), vec!["b/z.txt"]);
+    }
+
+    #[test]
+    fn test_no_match() {
+        let dir = setup("none", &["a"]);
+        assert!(matches(&dir, "*.rs").is_empty());
+    }
+
+    #[test]
+    fn test_quoted_metacharacters() {
+        let dir = setup("quoted", &["*", "ab"]);
+        let prefix = unquoted(&format!("{}/", dir.display()));
+        let pattern = [prefix, vec![('*', true)]].concat();
+        assert_eq!(glob(&pattern), vec![format!("{}/*", dir.display())]);
+    }
+
+    #[test]
+    fn test_globstar() {
+        let dir = setup(
+            "globstar",
+            &["x.rs", "a/y.rs", "a/b/z.rs", "a/b/z.txt", ".git/h.rs"],
+        );
+        assert_eq!(matches(&dir, "**/*.rs"), vec!["a/b/z.rs", "a/y.rs", "x.rs"]);
+        assert_eq!(
+            matches(&dir, "a/**"),
+            vec!["a/", "a/b", "a/b/z.rs", "a/b/z.txt", "a/y.rs"]
+        );
+        // The directory the pattern starts from is included.
+        assert_eq!(matches(&dir, "**/"), vec!["", "a/", "a/b/"]);
+    }
+
+    #[test]
+    fn test_globstar_symlink_loop() {
+        let dir = setup("loop", &["a/x.rs"]);
+        std::os::unix::fs::symlink("..", dir.join("a/up")).unwrap();
+        assert_eq!(matches(&dir, "**/*.rs"), vec!["a/x.rs"]);
+    }
+
+    #[test]
+    fn test_extglob() {
+        let dir = setup("extglob", &["a.rs", "b.md", "c.txt"]);
+        assert_eq!(matches(&dir, "!(*.rs)"), vec!["b.md", "c.txt"]);
+        assert_eq!(matches(&dir, "*.@(rs|md)"), vec!["a.rs", "b.md"]);
+    }
+}
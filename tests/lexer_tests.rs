@@ -0,0 +1,496 @@
+#[cfg(test)]
+mod tests {
+    use codecrafters_shell::expand::expand_word;
+    use codecrafters_shell::lexer::{needs_more_input, tokenize, HereDoc, Operator, Token};
+
+    fn word(s: &str) -> Token {
+        Token::Word(s.to_string())
+    }
+
+    /// Splits a line of plain words and removes their quoting, as the shell
+    /// does to get a command's arguments.
+    fn args(input: &str) -> Vec<String> {
+        let mut args = vec![];
+        for token in tokenize(input).unwrap() {
+            let Token::Word(word) = token else {
+                panic!("unexpected operator in {:?}", input);
+            };
+            args.extend(expand_word(&word).unwrap());
+        }
+        args
+    }
+
+    #[test]
+    fn test_empty_input() {
+        assert_eq!(tokenize("").unwrap(), vec![]);
+    }
+
+    #[test]
+    fn test_words() {
+        assert_eq!(
+            tokenize("  echo foo\tbar ").unwrap(),
+            vec![word("echo"), word("foo"), word("bar")]
+        );
+    }
+
+    #[test]
+    fn test_pipe() {
+        assert_eq!(
+            tokenize("ls|wc -l").unwrap(),
+            vec![
+                word("ls"),
+                Token::Op(Operator::Pipe),
+                word("wc"),
+                word("-l")
+            ]
+        );
+    }
+
+    #[test]
+    fn test_single_quoted_operator_is_literal() {
+        assert_eq!(
+            tokenize("echo 'a|b'").unwrap(),
+            vec![word("echo"), word("'a|b'")]
+        );
+    }
+
+    #[test]
+    fn test_double_quoted_operator_is_literal() {
+        assert_eq!(
+            tokenize(r#"grep "x|y;z" file"#).unwrap(),
+            vec![word("grep"), word(r#""x|y;z""#), word("file")]
+        );
+    }
+
+    #[test]
+    fn test_escaped_operator_is_literal() {
+        assert_eq!(
+            tokenize(r"echo a\|b \>").unwrap(),
+            vec![word("echo"), word(r"a\|b"), word(r"\>")]
+        );
+    }
+
+    #[test]
+    fn test_escaped_quote_in_double_quotes() {
+        assert_eq!(
+            tokenize(r#"echo "a\"|b""#).unwrap(),
+            vec![word("echo"), word(r#""a\"|b""#)]
+        );
+    }
+
+    #[test]
+    fn test_longest_operator_match() {
+        assert_eq!(
+            tokenize("a&&b||c;d&e").unwrap(),
+            vec![
+                word("a"),
+                Token::Op(Operator::AndIf),
+                word("b"),
+                Token::Op(Operator::OrIf),
+                word("c"),
+                Token::Op(Operator::Semi),
+                word("d"),
+                Token::Op(Operator::Amp),
+                word("e"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_case_item_operators() {
+        assert_eq!(
+            tokenize("a|b) c;; (d) e;").unwrap(),
+            vec![
+                word("a"),
+                Token::Op(Operator::Pipe),
+                word("b"),
+                Token::Op(Operator::RParen),
+                word("c"),
+                Token::Op(Operator::DSemi),
+                Token::Op(Operator::LParen),
+                word("d"),
+                Token::Op(Operator::RParen),
+                word("e"),
+                Token::Op(Operator::Semi),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_redirection_operators() {
+        assert_eq!(
+            tokenize("a >> b &>> c <<- d >| e").unwrap(),
+            vec![
+                word("a"),
+                Token::Op(Operator::DGreat),
+                word("b"),
+                Token::Op(Operator::AndDGreat),
+                word("c"),
+                Token::Op(Operator::DLessDash),
+                here_doc("d", "", true),
+                Token::Op(Operator::Clobber),
+                word("e"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_io_number() {
+        assert_eq!(
+            tokenize("cmd 2>err 2 >out").unwrap(),
+            vec![
+                word("cmd"),
+                Token::IoNumber(2),
+                Token::Op(Operator::Great),
+                word("err"),
+                word("2"),
+                Token::Op(Operator::Great),
+                word("out"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_io_number_requires_only_digits() {
+        assert_eq!(
+            tokenize("a2>b").unwrap(),
+            vec![word("a2"), Token::Op(Operator::Great), word("b")]
+        );
+    }
+
+    #[test]
+    fn test_newline_operator() {
+        assert_eq!(
+            tokenize("a\nb").unwrap(),
+            vec![word("a"), Token::Op(Operator::Newline), word("b")]
+        );
+    }
+
+    #[test]
+    fn test_line_continuation() {
+        assert_eq!(tokenize("ec\\\nho").unwrap(), vec![word("echo")]);
+    }
+
+    #[test]
+    fn test_comment() {
+        assert_eq!(
+            tokenize("echo a#b # c | d").unwrap(),
+            vec![word("echo"), word("a#b")]
+        );
+    }
+
+    #[test]
+    fn test_unterminated_quote() {
+        assert!(tokenize("echo 'abc").is_err());
+        assert!(tokenize(r#"echo "abc"#).is_err());
+    }
+
+    #[test]
+    fn test_braced_parameter_is_one_word() {
+        assert_eq!(
+            tokenize(r#"echo ${a:-x | y}"${b}|"|c"#).unwrap(),
+            vec![
+                word("echo"),
+                word(r#"${a:-x | y}"${b}|""#),
+                Token::Op(Operator::Pipe),
+                word("c"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_unterminated_braced_parameter() {
+        assert!(tokenize("echo ${a").is_err());
+    }
+
+    #[test]
+    fn test_command_substitution_is_one_word() {
+        assert_eq!(
+            tokenize(r#"echo $(ls | (wc; echo ")")) `a|b` "x$(y;z)""#).unwrap(),
+            vec![
+                word("echo"),
+                word(r#"$(ls | (wc; echo ")"))"#),
+                word("`a|b`"),
+                word(r#""x$(y;z)""#),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_unterminated_command_substitution() {
+        assert!(tokenize("echo $(ls").is_err());
+        assert!(tokenize("echo `ls").is_err());
+    }
+
+    #[test]
+    fn test_arith_command() {
+        assert_eq!(
+            tokenize("((x = (1 + 2) * 3)); ((a) )").unwrap(),
+            vec![
+                Token::Arith("x = (1 + 2) * 3".to_string()),
+                Token::Op(Operator::Semi),
+                Token::Op(Operator::LParen),
+                Token::Op(Operator::LParen),
+                word("a"),
+                Token::Op(Operator::RParen),
+                Token::Op(Operator::RParen),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_arithmetic_expansion_is_one_word() {
+        assert_eq!(
+            tokenize("echo $((1 + (2)))").unwrap(),
+            vec![word("echo"), word("$((1 + (2)))")]
+        );
+    }
+
+    #[test]
+    fn test_extglob_is_one_word() {
+        assert_eq!(
+            tokenize("ls !(*.rs) *.@(a|b) +(x)").unwrap(),
+            vec![word("ls"), word("!(*.rs)"), word("*.@(a|b)"), word("+(x)")]
+        );
+    }
+
+    fn here_doc(delimiter: &str, body: &str, expand: bool) -> Token {
+        let body = body.to_string();
+        Token::HereDoc(delimiter.to_string(), HereDoc { body, expand })
+    }
+
+    #[test]
+    fn test_here_doc_body_follows_the_line() {
+        assert_eq!(
+            tokenize("cat <<EOF; echo x\na 'b'\nEOF\necho y").unwrap(),
+            vec![
+                word("cat"),
+                Token::Op(Operator::DLess),
+                here_doc("EOF", "a 'b'\n", true),
+                Token::Op(Operator::Semi),
+                word("echo"),
+                word("x"),
+                Token::Op(Operator::Newline),
+                word("echo"),
+                word("y"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_here_docs_on_one_line() {
+        assert_eq!(
+            tokenize("cat <<A <<B\n1\nA\n2\nB\n").unwrap(),
+            vec![
+                word("cat"),
+                Token::Op(Operator::DLess),
+                here_doc("A", "1\n", true),
+                Token::Op(Operator::DLess),
+                here_doc("B", "2\n", true),
+                Token::Op(Operator::Newline),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_quoted_here_doc_delimiter() {
+        for delimiter in ["'EOF'", "\"EOF\"", "\\EOF", "E'O'F"] {
+            assert_eq!(
+                tokenize(&format!("cat <<{}\n$x\nEOF", delimiter)).unwrap()[2],
+                here_doc(delimiter, "$x\n", false)
+            );
+        }
+    }
+
+    #[test]
+    fn test_here_doc_strip_tabs() {
+        assert_eq!(
+            tokenize("cat <<-EOF\n\t\ta\n  b\n\tEOF\n").unwrap()[2],
+            here_doc("EOF", "a\n  b\n", true)
+        );
+    }
+
+    #[test]
+    fn test_needs_more_input() {
+        assert!(needs_more_input("cat <<EOF"));
+        assert!(needs_more_input("cat <<EOF\nline"));
+        assert!(needs_more_input("cat <<EOF\nEOFx\n"));
+        assert!(!needs_more_input("cat <<EOF\nline\nEOF"));
+        assert!(!needs_more_input("echo '<<EOF'"));
+        assert!(!needs_more_input("echo 'unterminated"));
+    }
+
+    #[test]
+    fn test_here_string_operator() {
+        assert_eq!(
+            tokenize("cat<<<\"$x\" 3<<<y").unwrap(),
+            vec![
+                word("cat"),
+                Token::Op(Operator::TLess),
+                word("\"$x\""),
+                Token::IoNumber(3),
+                Token::Op(Operator::TLess),
+                word("y"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_process_substitution_is_one_word() {
+        assert_eq!(
+            tokenize("diff <(sort a | uniq) >(cat) 2>(x)").unwrap(),
+            vec![
+                word("diff"),
+                word("<(sort a | uniq)"),
+                word(">(cat)"),
+                Token::IoNumber(2),
+                Token::Op(Operator::Great),
+                Token::Op(Operator::LParen),
+                word("x"),
+                Token::Op(Operator::RParen),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_pipe_and_operator() {
+        assert_eq!(
+            tokenize("a|&b || c").unwrap(),
+            vec![
+                word("a"),
+                Token::Op(Operator::PipeAnd),
+                word("b"),
+                Token::Op(Operator::OrIf),
+                word("c"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_args_empty_input() {
+        assert_eq!(args(""), Vec::<String>::new());
+    }
+
+    #[test]
+    fn test_args_single_argument() {
+        assert_eq!(args("foo"), vec!["foo".to_string()]);
+    }
+
+    #[test]
+    fn test_args_multiple_arguments() {
+        assert_eq!(
+            args("foo bar baz"),
+            vec!["foo".to_string(), "bar".to_string(), "baz".to_string()]
+        );
+    }
+
+    #[test]
+    fn test_args_leading_trailing_whitespace() {
+        assert_eq!(
+            args("  foo bar  "),
+            vec!["foo".to_string(), "bar".to_string()]
+        );
+    }
+
+    #[test]
+    fn test_args_tabs_as_whitespace() {
+        assert_eq!(args("foo\tbar"), vec!["foo".to_string(), "bar".to_string()]);
+    }
+
+    #[test]
+    fn test_args_double_quoted_argument() {
+        assert_eq!(args(r#""foo bar""#), vec!["foo bar".to_string()]);
+    }
+
+    #[test]
+    fn test_args_double_quoted_with_escaped_quote() {
+        assert_eq!(args(r#""foo\"bar""#), vec![r#"foo"bar"#.to_string()]);
+    }
+
+    #[test]
+    fn test_args_double_quoted_with_escaped_backslash() {
+        assert_eq!(args(r#""foo\\bar""#), vec![r#"foo\bar"#.to_string()]);
+    }
+
+    #[test]
+    fn test_args_double_quoted_with_unescaped_backslash_non_special() {
+        assert_eq!(args(r#""foo\bar""#), vec![r#"foo\bar"#.to_string()]);
+    }
+
+    #[test]
+    fn test_args_double_quoted_with_single_quote_inside() {
+        assert_eq!(args(r#""foo'bar""#), vec!["foo'bar".to_string()]);
+    }
+
+    #[test]
+    fn test_args_single_quoted_argument() {
+        assert_eq!(args(r#"'foo bar'"#), vec!["foo bar".to_string()]);
+    }
+
+    #[test]
+    fn test_args_single_quoted_with_backslash() {
+        assert_eq!(args(r#"'foo\bar'"#), vec![r#"foo\bar"#.to_string()]);
+    }
+
+    #[test]
+    fn test_args_single_quoted_with_double_quote_inside() {
+        assert_eq!(args(r#"'foo"bar'"#), vec![r#"foo"bar"#.to_string()]);
+    }
+
+    #[test]
+    fn test_args_single_quoted_with_embedded_single_quote() {
+        assert_eq!(args(r#"'foo''bar'"#), vec!["foobar".to_string()]);
+    }
+
+    #[test]
+    fn test_args_escaped_space_unquoted() {
+        assert_eq!(args(r"foo\ bar"), vec!["foo bar".to_string()]);
+    }
+
+    #[test]
+    fn test_args_escaped_backslash_unquoted() {
+        assert_eq!(args(r"foo\\bar"), vec![r#"foo\bar"#.to_string()]);
+    }
+
+    #[test]
+    fn test_args_unquoted_backslash_non_special() {
+        assert_eq!(args(r"foo\bar"), vec!["foobar".to_string()]);
+    }
+
+    #[test]
+    fn test_args_mixed_quoting() {
+        assert_eq!(
+            args(r#"foo "bar baz" 'qux'"#),
+            vec!["foo".to_string(), "bar baz".to_string(), "qux".to_string()]
+        );
+    }
+
+    #[test]
+    fn test_args_nested_quotes_combination() {
+        assert_eq!(
+            args(r#""foo 'bar' \"baz\"""#),
+            vec![r#"foo 'bar' "baz""#.to_string()]
+        );
+    }
+
+    #[test]
+    fn test_args_real_cat_command_args() {
+        assert_eq!(
+            args(r#"'./tmp/bar/f    25' './tmp/bar/f    25'"#),
+            vec![
+                r#"./tmp/bar/f    25"#.to_string(),
+                r#"./tmp/bar/f    25"#.to_string()
+            ]
+        );
+    }
+
+    #[test]
+    fn test_args_escaped_quotes_in_unquoted() {
+        assert_eq!(args(r#"foo\"bar"#), vec![r#"foo"bar"#.to_string()]);
+    }
+
+    #[test]
+    fn test_args_multiple_escapes() {
+        assert_eq!(args(r"a\\b\ c"), vec![r"a\b c".to_string()]);
+    }
+}
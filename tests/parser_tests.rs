@@ -0,0 +1,550 @@
+#[cfg(test)]
+mod tests {
+    use codecrafters_shell::lexer::{HereDoc, Operator};
+    use codecrafters_shell::parser::{
+        is_incomplete, parse, AndOrList, Assignment, CaseItem, CommandNode, CompoundCommand,
+        Connector, FunctionDef, List, Pipeline, Redirect, SimpleCommand,
+    };
+
+    fn simple(words: &[&str]) -> CommandNode {
+        CommandNode::Simple(SimpleCommand {
+            words: words.iter().map(|w| w.to_string()).collect(),
+            ..Default::default()
+        })
+    }
+
+    fn pipeline(commands: Vec<CommandNode>) -> Pipeline {
+        Pipeline {
+            commands,
+            negated: false,
+        }
+    }
+
+    fn single(commands: Vec<CommandNode>) -> AndOrList {
+        AndOrList {
+            first: pipeline(commands),
+            rest: vec![],
+            background: false,
+        }
+    }
+
+    /// A list of single-word commands, one after another.
+    fn commands(names: &[&str]) -> List {
+        List(
+            names
+                .iter()
+                .map(|name| single(vec![simple(&[name])]))
+                .collect(),
+        )
+    }
+
+    fn compound(compound: CompoundCommand) -> List {
+        List(vec![single(vec![CommandNode::Compound(compound, vec![])])])
+    }
+
+    #[test]
+    fn test_empty_input() {
+        assert_eq!(parse("").unwrap(), List(vec![]));
+        assert_eq!(parse(" \n \n").unwrap(), List(vec![]));
+    }
+
+    #[test]
+    fn test_simple_command() {
+        assert_eq!(
+            parse("echo 'a b' c").unwrap(),
+            List(vec![single(vec![simple(&["echo", "'a b'", "c"])])])
+        );
+    }
+
+    #[test]
+    fn test_pipeline() {
+        assert_eq!(
+            parse("cat f | grep 'x|y' | wc -l").unwrap(),
+            List(vec![single(vec![
+                simple(&["cat", "f"]),
+                simple(&["grep", "'x|y'"]),
+                simple(&["wc", "-l"]),
+            ])])
+        );
+    }
+
+    #[test]
+    fn test_sequential_list() {
+        assert_eq!(
+            parse("cd dir; ls\npwd;").unwrap(),
+            List(vec![
+                single(vec![simple(&["cd", "dir"])]),
+                single(vec![simple(&["ls"])]),
+                single(vec![simple(&["pwd"])]),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_and_or_list() {
+        assert_eq!(
+            parse("make && ./run ||\necho failed").unwrap(),
+            List(vec![AndOrList {
+                first: pipeline(vec![simple(&["make"])]),
+                rest: vec![
+                    (Connector::And, pipeline(vec![simple(&["./run"])])),
+                    (Connector::Or, pipeline(vec![simple(&["echo", "failed"])])),
+                ],
+                background: false,
+            }])
+        );
+    }
+
+    #[test]
+    fn test_redirections_are_nodes() {
+        assert_eq!(
+            parse("echo > out hi 2>>err").unwrap(),
+            List(vec![single(vec![CommandNode::Simple(SimpleCommand {
+                words: vec!["echo".to_string(), "hi".to_string()],
+                redirects: vec![
+                    Redirect {
+                        fd: None,
+                        op: Operator::Great,
+                        target: "out".to_string(),
+                        here_doc: None,
+                    },
+                    Redirect {
+                        fd: Some(2),
+                        op: Operator::DGreat,
+                        target: "err".to_string(),
+                        here_doc: None,
+                    },
+                ],
+                ..Default::default()
+            })])])
+        );
+    }
+
+    #[test]
+    fn test_quoted_operator_is_a_word() {
+        assert_eq!(
+            parse("echo '>' x").unwrap(),
+            List(vec![single(vec![simple(&["echo", "'>'", "x"])])])
+        );
+    }
+
+    #[test]
+    fn test_brace_group_with_redirect() {
+        assert_eq!(
+            parse("{ echo a; echo b; } > out").unwrap(),
+            List(vec![single(vec![CommandNode::Compound(
+                CompoundCommand::BraceGroup(List(vec![
+                    single(vec![simple(&["echo", "a"])]),
+                    single(vec![simple(&["echo", "b"])]),
+                ])),
+                vec![Redirect {
+                    fd: None,
+                    op: Operator::Great,
+                    target: "out".to_string(),
+                    here_doc: None,
+                }],
+            )])])
+        );
+    }
+
+    #[test]
+    fn test_subshell_in_pipeline() {
+        assert_eq!(
+            parse("(cd /; ls) | wc").unwrap(),
+            List(vec![single(vec![
+                CommandNode::Compound(
+                    CompoundCommand::Subshell(List(vec![
+                        single(vec![simple(&["cd", "/"])]),
+                        single(vec![simple(&["ls"])]),
+                    ])),
+                    vec![],
+                ),
+                simple(&["wc"]),
+            ])])
+        );
+    }
+
+    #[test]
+    fn test_closing_brace_as_argument() {
+        assert_eq!(
+            parse("echo }").unwrap(),
+            List(vec![single(vec![simple(&["echo", "}"])])])
+        );
+    }
+
+    #[test]
+    fn test_syntax_errors() {
+        assert!(parse("| ls").is_err());
+        assert!(parse("ls |").is_err());
+        assert!(parse("ls && ").is_err());
+        assert!(parse("echo >").is_err());
+        assert!(parse("{ echo a; ").is_err());
+        assert!(parse("{ }").is_err());
+        assert!(parse("(ls))").is_err());
+        assert!(parse(";").is_err());
+    }
+
+    #[test]
+    fn test_prefix_assignments() {
+        assert_eq!(
+            parse("A=1 B='x y' cmd C=2").unwrap(),
+            List(vec![single(vec![CommandNode::Simple(SimpleCommand {
+                assignments: vec![
+                    Assignment {
+                        name: "A".to_string(),
+                        value: "1".to_string(),
+                    },
+                    Assignment {
+                        name: "B".to_string(),
+                        value: "'x y'".to_string(),
+                    },
+                ],
+                words: vec!["cmd".to_string(), "C=2".to_string()],
+                ..Default::default()
+            })])])
+        );
+    }
+
+    #[test]
+    fn test_assignment_requires_valid_name() {
+        assert_eq!(
+            parse("1A=x '=y' 'B'=z").unwrap(),
+            List(vec![single(vec![simple(&["1A=x", "'=y'", "'B'=z"])])])
+        );
+    }
+
+    #[test]
+    fn test_arith_command() {
+        assert_eq!(
+            parse("((i++)) > out").unwrap(),
+            List(vec![single(vec![CommandNode::Compound(
+                CompoundCommand::Arith("i++".to_string()),
+                vec![Redirect {
+                    fd: None,
+                    op: Operator::Great,
+                    target: "out".to_string(),
+                    here_doc: None,
+                }],
+            )])])
+        );
+    }
+
+    fn redirect(fd: Option<u32>, op: Operator, target: &str) -> Redirect {
+        Redirect {
+            fd,
+            op,
+            target: target.to_string(),
+            here_doc: None,
+        }
+    }
+
+    #[test]
+    fn test_redirections_before_and_between_words() {
+        assert_eq!(
+            parse("2>err A=1 >out cmd a <in b").unwrap(),
+            List(vec![single(vec![CommandNode::Simple(SimpleCommand {
+                assignments: vec![Assignment {
+                    name: "A".to_string(),
+                    value: "1".to_string(),
+                }],
+                words: vec!["cmd".to_string(), "a".to_string(), "b".to_string()],
+                redirects: vec![
+                    redirect(Some(2), Operator::Great, "err"),
+                    redirect(None, Operator::Great, "out"),
+                    redirect(None, Operator::Less, "in"),
+                ],
+            })])])
+        );
+    }
+
+    #[test]
+    fn test_redirection_only_command() {
+        assert_eq!(
+            parse("> out").unwrap(),
+            List(vec![single(vec![CommandNode::Simple(SimpleCommand {
+                redirects: vec![redirect(None, Operator::Great, "out")],
+                ..Default::default()
+            })])])
+        );
+    }
+
+    #[test]
+    fn test_redirection_needs_a_target() {
+        assert_eq!(
+            parse("echo hi >").unwrap_err().to_string(),
+            "syntax error near unexpected token `newline`"
+        );
+        assert_eq!(
+            parse("echo > | cat").unwrap_err().to_string(),
+            "syntax error near unexpected token `|`"
+        );
+        assert_eq!(
+            parse("echo > > x").unwrap_err().to_string(),
+            "syntax error near unexpected token `>`"
+        );
+    }
+
+    #[test]
+    fn test_here_doc_redirect() {
+        assert_eq!(
+            parse("cat <<EOF 3<<-'END'\nhi $x\nEOF\n\tbye\n\tEND\n").unwrap(),
+            List(vec![single(vec![CommandNode::Simple(SimpleCommand {
+                words: vec!["cat".to_string()],
+                redirects: vec![
+                    Redirect {
+                        fd: None,
+                        op: Operator::DLess,
+                        target: "EOF".to_string(),
+                        here_doc: Some(HereDoc {
+                            body: "hi $x\n".to_string(),
+                            expand: true,
+                        }),
+                    },
+                    Redirect {
+                        fd: Some(3),
+                        op: Operator::DLessDash,
+                        target: "'END'".to_string(),
+                        here_doc: Some(HereDoc {
+                            body: "bye\n".to_string(),
+                            expand: false,
+                        }),
+                    },
+                ],
+                ..Default::default()
+            })])])
+        );
+    }
+
+    #[test]
+    fn test_pipe_and_redirects_stderr_last() {
+        assert_eq!(
+            parse("a 2>err |& { b; } |& c").unwrap(),
+            List(vec![single(vec![
+                CommandNode::Simple(SimpleCommand {
+                    words: vec!["a".to_string()],
+                    redirects: vec![
+                        redirect(Some(2), Operator::Great, "err"),
+                        redirect(Some(2), Operator::GreatAnd, "1"),
+                    ],
+                    ..Default::default()
+                }),
+                CommandNode::Compound(
+                    CompoundCommand::BraceGroup(List(vec![single(vec![simple(&["b"])])])),
+                    vec![redirect(Some(2), Operator::GreatAnd, "1")],
+                ),
+                simple(&["c"]),
+            ])])
+        );
+    }
+
+    #[test]
+    fn test_background_lists() {
+        let background = |commands| AndOrList {
+            background: true,
+            ..single(commands)
+        };
+        assert_eq!(
+            parse("sleep 1 & echo a; b &\nc").unwrap(),
+            List(vec![
+                background(vec![simple(&["sleep", "1"])]),
+                single(vec![simple(&["echo", "a"])]),
+                background(vec![simple(&["b"])]),
+                single(vec![simple(&["c"])]),
+            ])
+        );
+        assert!(parse("a & ;").is_err());
+        assert!(parse("& a").is_err());
+    }
+
+    #[test]
+    fn test_display() {
+        for source in [
+            "A=1 echo 'a b' \"$x\" >out 2>&1",
+            "a && b | c || d",
+            "{ a; b & } <in",
+            "(a; b) | ((i++)) 3<<EOF",
+            "x & y; z",
+            "! a | b",
+            "if a; then b; elif c; then d & else e; fi",
+            "while a; do b; done",
+            "until a; do b; done >out",
+            "for x in a 'b c'; do echo $x; done",
+            "for x; do echo $x; done",
+            "case $x in a|b) c;; *) ;; esac",
+            "f() { a; }",
+        ] {
+            assert_eq!(parse(source).unwrap().to_string(), source);
+        }
+    }
+
+    #[test]
+    fn test_if() {
+        assert_eq!(
+            parse("if a; then b; elif c\nthen d; else e; fi").unwrap(),
+            compound(CompoundCommand::If {
+                branches: vec![
+                    (commands(&["a"]), commands(&["b"])),
+                    (commands(&["c"]), commands(&["d"])),
+                ],
+                otherwise: Some(commands(&["e"])),
+            })
+        );
+        assert_eq!(
+            parse("if a\nthen\n  b\n  c\nfi").unwrap(),
+            compound(CompoundCommand::If {
+                branches: vec![(commands(&["a"]), commands(&["b", "c"]))],
+                otherwise: None,
+            })
+        );
+    }
+
+    #[test]
+    fn test_while_and_until() {
+        assert_eq!(
+            parse("while a; b; do c; done").unwrap(),
+            compound(CompoundCommand::Loop {
+                condition: commands(&["a", "b"]),
+                body: commands(&["c"]),
+                until: false,
+            })
+        );
+        assert_eq!(
+            parse("until a\ndo\nc\ndone").unwrap(),
+            compound(CompoundCommand::Loop {
+                condition: commands(&["a"]),
+                body: commands(&["c"]),
+                until: true,
+            })
+        );
+    }
+
+    #[test]
+    fn test_for() {
+        let for_x = |words: Option<&[&str]>| {
+            compound(CompoundCommand::For {
+                name: "x".to_string(),
+                words: words.map(|words| words.iter().map(|w| w.to_string()).collect()),
+                body: commands(&["a"]),
+            })
+        };
+        assert_eq!(
+            parse("for x in b \"$c\"; do a; done").unwrap(),
+            for_x(Some(&["b", "\"$c\""]))
+        );
+        assert_eq!(parse("for x in\ndo a; done").unwrap(), for_x(Some(&[])));
+        assert_eq!(parse("for x; do a; done").unwrap(), for_x(None));
+        assert_eq!(parse("for x\ndo a; done").unwrap(), for_x(None));
+    }
+
+    #[test]
+    fn test_case() {
+        assert_eq!(
+            parse("case $x in\n  a|'b')\n    c\n    ;;\n  (*) ;;\n  d) e\nesac").unwrap(),
+            compound(CompoundCommand::Case {
+                word: "$x".to_string(),
+                items: vec![
+                    CaseItem {
+                        patterns: vec!["a".to_string(), "'b'".to_string()],
+                        body: commands(&["c"]),
+                    },
+                    CaseItem {
+                        patterns: vec!["*".to_string()],
+                        body: List(vec![]),
+                    },
+                    CaseItem {
+                        patterns: vec!["d".to_string()],
+                        body: commands(&["e"]),
+                    },
+                ],
+            })
+        );
+        assert_eq!(
+            parse("case x in esac").unwrap(),
+            compound(CompoundCommand::Case {
+                word: "x".to_string(),
+                items: vec![],
+            })
+        );
+    }
+
+    #[test]
+    fn test_function_definition() {
+        assert_eq!(
+            parse("f() { a; } >out; f").unwrap(),
+            List(vec![
+                single(vec![CommandNode::Function(FunctionDef {
+                    name: "f".to_string(),
+                    body: CompoundCommand::BraceGroup(commands(&["a"])),
+                    redirects: vec![redirect(None, Operator::Great, "out")],
+                })]),
+                single(vec![simple(&["f"])]),
+            ])
+        );
+        assert_eq!(
+            parse("g ()\n(a)").unwrap(),
+            List(vec![single(vec![CommandNode::Function(FunctionDef {
+                name: "g".to_string(),
+                body: CompoundCommand::Subshell(commands(&["a"])),
+                redirects: vec![],
+            })])])
+        );
+    }
+
+    #[test]
+    fn test_negated_pipeline() {
+        assert_eq!(
+            parse("! a | b && c").unwrap(),
+            List(vec![AndOrList {
+                first: Pipeline {
+                    commands: vec![simple(&["a"]), simple(&["b"])],
+                    negated: true,
+                },
+                rest: vec![(Connector::And, pipeline(vec![simple(&["c"])]))],
+                background: false,
+            }])
+        );
+    }
+
+    #[test]
+    fn test_reserved_words_only_start_commands() {
+        assert_eq!(
+            parse("echo if then fi done").unwrap(),
+            List(vec![single(vec![simple(&[
+                "echo", "if", "then", "fi", "done"
+            ])])])
+        );
+        assert_eq!(
+            parse("'if' a").unwrap(),
+            List(vec![single(vec![simple(&["'if'", "a"])])])
+        );
+    }
+
+    #[test]
+    fn test_compound_syntax_errors() {
+        assert!(parse("if a; fi").is_err());
+        assert!(parse("if then b; fi").is_err());
+        assert!(parse("if a; then b; else fi").is_err());
+        assert!(parse("while a; done").is_err());
+        assert!(parse("for 1x in a; do b; done").is_err());
+        assert!(parse("for x in a do b; done").is_err());
+        assert!(parse("case a in b) c;; ;; esac").is_err());
+        assert!(parse("case a b) c;; esac").is_err());
+        assert!(parse("f() echo").is_err());
+        assert!(parse("fi").is_err());
+        assert!(parse("echo a;; echo b").is_err());
+    }
+
+    #[test]
+    fn test_is_incomplete() {
+        assert!(is_incomplete("if a; then"));
+        assert!(is_incomplete("if a; then b; fi; while c"));
+        assert!(is_incomplete("for x in a b; do"));
+        assert!(is_incomplete("case a in b)"));
+        assert!(is_incomplete("f() {"));
+        assert!(is_incomplete("a &&"));
+        assert!(is_incomplete("cat <<EOF"));
+        assert!(!is_incomplete("if a; then b; fi"));
+        assert!(!is_incomplete("if a; fi"));
+        assert!(!is_incomplete("echo >"));
+    }
+}
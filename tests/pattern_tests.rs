@@ -0,0 +1,92 @@
+#[cfg(test)]
+mod tests {
+    use codecrafters_shell::{options::set_shopt, pattern::Pattern};
+
+    fn pattern(s: &str) -> Pattern {
+        set_shopt("extglob", true).unwrap();
+        let chars: Vec<(char, bool)> = s.chars().map(|c| (c, false)).collect();
+        Pattern::new(&chars)
+    }
+
+    #[test]
+    fn test_literal() {
+        assert!(pattern("abc").matches("abc"));
+        assert!(!pattern("abc").matches("abd"));
+        assert!(!pattern("abc").has_wildcards());
+    }
+
+    #[test]
+    fn test_star() {
+        assert!(pattern("*.rs").matches("main.rs"));
+        assert!(pattern("*.rs").matches(".rs"));
+        assert!(pattern("a*b*c").matches("aXbYbc"));
+        assert!(!pattern("*.rs").matches("main.rs.bak"));
+    }
+
+    #[test]
+    fn test_question_mark() {
+        assert!(pattern("?.txt").matches("a.txt"));
+        assert!(!pattern("?.txt").matches("ab.txt"));
+    }
+
+    #[test]
+    fn test_bracket_expression() {
+        assert!(pattern("[abc]x").matches("bx"));
+        assert!(pattern("[a-c]x").matches("cx"));
+        assert!(!pattern("[a-c]x").matches("dx"));
+        assert!(pattern("[!a-c]x").matches("dx"));
+        assert!(pattern("[^a-c]x").matches("dx"));
+        assert!(pattern("[]]").matches("]"));
+        assert!(pattern("[a-]").matches("-"));
+    }
+
+    #[test]
+    fn test_named_class() {
+        assert!(pattern("[[:digit:]][[:alpha:]]").matches("1a"));
+        assert!(!pattern("[[:digit:]]").matches("a"));
+    }
+
+    #[test]
+    fn test_unclosed_bracket_is_literal() {
+        assert!(pattern("[ab").matches("[ab"));
+    }
+
+    #[test]
+    fn test_quoted_characters_are_literal() {
+        let chars = vec![('*', true), ('.', false), ('?', true)];
+        let pattern = Pattern::new(&chars);
+        assert!(pattern.matches("*.?"));
+        assert!(!pattern.matches("a.b"));
+        assert!(!pattern.has_wildcards());
+    }
+
+    #[test]
+    fn test_extglob_exactly_one() {
+        assert!(pattern("@(foo|bar).rs").matches("bar.rs"));
+        assert!(!pattern("@(foo|bar).rs").matches("foobar.rs"));
+        assert!(pattern("@(foo|bar).rs").has_wildcards());
+    }
+
+    #[test]
+    fn test_extglob_repetition() {
+        assert!(pattern("+(ab|c)").matches("abcab"));
+        assert!(!pattern("+(ab|c)").matches(""));
+        assert!(pattern("*(ab|c)").matches(""));
+        assert!(pattern("x?(y)z").matches("xz"));
+        assert!(pattern("x?(y)z").matches("xyz"));
+        assert!(!pattern("x?(y)z").matches("xyyz"));
+    }
+
+    #[test]
+    fn test_extglob_negation() {
+        assert!(pattern("!(*.rs)").matches("README.md"));
+        assert!(!pattern("!(*.rs)").matches("main.rs"));
+        assert!(pattern("!(a|b)").matches("c"));
+    }
+
+    #[test]
+    fn test_extglob_nested_and_unclosed() {
+        assert!(pattern("@(a+(b)|c)").matches("abbb"));
+        assert!(pattern("@(a").matches("@(a"));
+    }
+}
@@ -0,0 +1,955 @@
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+    use std::process::{Command, Stdio};
+
+    fn run(script: &str) -> String {
+        let mut child = Command::new(env!("CARGO_BIN_EXE_codecrafters-shell"))
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .unwrap();
+        child
+            .stdin
+            .take()
+            .unwrap()
+            .write_all(script.as_bytes())
+            .unwrap();
+        let output = child.wait_with_output().unwrap();
+        String::from_utf8(output.stdout).unwrap()
+    }
+
+    /// Runs a script and returns what it wrote to stdout and to stderr.
+    fn run_with_stderr(script: &str) -> (String, String) {
+        let mut child = Command::new(env!("CARGO_BIN_EXE_codecrafters-shell"))
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .unwrap();
+        child
+            .stdin
+            .take()
+            .unwrap()
+            .write_all(script.as_bytes())
+            .unwrap();
+        let output = child.wait_with_output().unwrap();
+        (
+            String::from_utf8(output.stdout).unwrap(),
+            String::from_utf8(output.stderr).unwrap(),
+        )
+    }
+
+    #[test]
+    fn test_sequential_list() {
+        assert_eq!(run("echo a; echo b\n"), "a\nb\n");
+    }
+
+    #[test]
+    fn test_and_list_short_circuits() {
+        assert_eq!(run("false && echo no\ntrue && echo yes\n"), "yes\n");
+    }
+
+    #[test]
+    fn test_or_list_short_circuits() {
+        assert_eq!(run("true || echo no\nfalse || echo yes\n"), "yes\n");
+    }
+
+    #[test]
+    fn test_mixed_and_or_list() {
+        assert_eq!(run("false && echo a || echo failed\n"), "failed\n");
+        assert_eq!(run("true && echo a || echo failed\n"), "a\n");
+    }
+
+    #[test]
+    fn test_and_or_uses_last_pipeline_stage() {
+        assert_eq!(run("false | true && echo ok\n"), "ok\n");
+    }
+
+    #[test]
+    fn test_command_not_found_fails() {
+        assert_eq!(run("no_such_command_xyz || echo missing\n"), "missing\n");
+    }
+
+    #[test]
+    fn test_brace_group_status() {
+        assert_eq!(run("{ true; false; } || echo failed\n"), "failed\n");
+    }
+
+    #[test]
+    fn test_if() {
+        assert_eq!(run("if true; then echo y; fi\n"), "y\n");
+        assert_eq!(run("if false; then echo y; fi; echo $?\n"), "0\n");
+        let script = "for x in 1 2 3; do\n\
+                      if [ $x = 1 ]; then echo one\n\
+                      elif [ $x = 2 ]; then echo two\n\
+                      else echo other; fi\n\
+                      done\n";
+        assert_eq!(run(script), "one\ntwo\nother\n");
+    }
+
+    #[test]
+    fn test_multi_line_compound_commands() {
+        let script = "if false\n\
+                      then\n\
+                      echo not run\n\
+                      fi\n\
+                      f() {\n\
+                      echo in f\n\
+                      }\n\
+                      f\n";
+        assert_eq!(run(script), "in f\n");
+    }
+
+    #[test]
+    fn test_while_and_until() {
+        let script = "i=0\n\
+                      while [ $i -lt 5 ]; do\n\
+                      i=$((i + 1))\n\
+                      [ $i = 2 ] && continue\n\
+                      [ $i = 4 ] && break\n\
+                      echo $i\n\
+                      done\n\
+                      until [ $i = 0 ]; do i=$((i - 1)); done; echo $i $?\n";
+        assert_eq!(run(script), "1\n3\n0 0\n");
+    }
+
+    #[test]
+    fn test_for() {
+        let script = "for x in a \"b c\" {1..2}; do echo \"[$x]\"; done\n\
+                      for x in 1 2; do for y in a b; do\n\
+                      [ $y = b ] && continue 2; echo $x$y\n\
+                      done; done\n\
+                      for x in 1 2; do for y in a b; do break 2; done; done; echo $x$y\n\
+                      for x in; do echo no; done; echo $?\n";
+        assert_eq!(run(script), "[a]\n[b c]\n[1]\n[2]\n1a\n2a\n1a\n0\n");
+    }
+
+    #[test]
+    fn test_case() {
+        let script = "for f in a.c b.h c '*'; do\n\
+                      case $f in\n\
+                      *.c | *.h) echo \"$f source\";;\n\
+                      \"*\") echo star ;;\n\
+                      (*) echo \"$f other\"\n\
+                      esac\n\
+                      done\n\
+                      case x in y) echo no;; esac; echo $?\n";
+        assert_eq!(run(script), "a.c source\nb.h source\nc other\nstar\n0\n");
+    }
+
+    #[test]
+    fn test_functions() {
+        let script = "greet() { echo \"hi $1 ($#)\"; return 3; echo no; }\n\
+                      greet you there; echo $?\n\
+                      echo \"[$1] $#\"\n\
+                      args() { for a; do echo \"<$a>\"; done; }\n\
+                      args 1 '2 3'\n\
+                      out() { echo redirected; } >&2\n\
+                      out 2>&1\n\
+                      type greet\n\
+                      count() ( echo sub $1 )\n\
+                      count x | cat\n";
+        assert_eq!(
+            run(script),
+            "hi you (2)\n3\n[] 0\n<1>\n<2 3>\nredirected\ngreet is a function\nsub x\n"
+        );
+    }
+
+    #[test]
+    fn test_return_and_break_outside_their_commands() {
+        let (stdout, stderr) =
+            run_with_stderr("return 1; echo $?\nbreak; echo $?\nf() { (return 4); echo $?; }; f\n");
+        assert_eq!(stdout, "1\n1\n4\n");
+        assert!(stderr.contains("return: can only `return' from a function"));
+        assert!(stderr.contains("break: only meaningful in a `for', `while', or `until' loop"));
+    }
+
+    #[test]
+    fn test_negated_pipeline() {
+        assert_eq!(
+            run("! false; echo $?\n! true; echo $?\n! echo a | grep -q b && echo none\n"),
+            "0\n1\nnone\n"
+        );
+    }
+
+    #[test]
+    fn test_exit_status_variable() {
+        assert_eq!(run("true; echo $?\nfalse; echo $?\n"), "0\n1\n");
+    }
+
+    #[test]
+    fn test_exit_status_of_missing_command() {
+        assert_eq!(run("no_such_command_xyz; echo $?\n"), "127\n");
+    }
+
+    #[test]
+    fn test_exit_status_of_signalled_child() {
+        assert_eq!(run("sh -c 'kill -9 $$'; echo $?\n"), "137\n");
+    }
+
+    #[test]
+    fn test_exit_status_of_builtins() {
+        assert_eq!(
+            run("cd /no/such/dir; echo $?\ntype no_such_command_xyz; echo $?\n"),
+            "1\nno_such_command_xyz: not found\n1\n"
+        );
+    }
+
+    #[test]
+    fn test_exit_status_quoting() {
+        assert_eq!(run("false; echo '$?' \"$?\" \\$?\n"), "$? 1 $?\n");
+    }
+
+    #[test]
+    fn test_exit_uses_last_status() {
+        let status = Command::new(env!("CARGO_BIN_EXE_codecrafters-shell"))
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+            .and_then(|mut child| {
+                child
+                    .stdin
+                    .take()
+                    .unwrap()
+                    .write_all(b"sh -c 'exit 7'\nexit\n")?;
+                child.wait()
+            })
+            .unwrap();
+        assert_eq!(status.code(), Some(7));
+    }
+
+    #[test]
+    fn test_exit_in_subshell_ends_only_the_subshell() {
+        assert_eq!(run("(exit 3); echo $?; echo after\n"), "3\nafter\n");
+        assert_eq!(run("(echo in; exit; echo no); echo after\n"), "in\nafter\n");
+    }
+
+    #[test]
+    fn test_exit_in_pipeline_ends_only_its_stage() {
+        assert_eq!(run("exit 5 | cat; echo after\n"), "after\n");
+        assert_eq!(run("true | { exit 4; echo no; }; echo $?\n"), "4\n");
+    }
+
+    #[test]
+    fn test_environment_variable_expansion() {
+        let home = std::env::var("HOME").unwrap();
+        assert_eq!(
+            run("echo $HOME \"${HOME}\" '$HOME'\n"),
+            format!("{home} {home} $HOME\n")
+        );
+    }
+
+    #[test]
+    fn test_shell_variable_is_not_exported() {
+        assert_eq!(
+            run("FOO=bar\necho $FOO\nsh -c 'echo [$FOO]'\n"),
+            "bar\n[]\n"
+        );
+    }
+
+    #[test]
+    fn test_export() {
+        assert_eq!(
+            run("FOO=bar; export FOO; export BAZ=qux\nsh -c 'echo $FOO $BAZ'\n"),
+            "bar qux\n"
+        );
+    }
+
+    #[test]
+    fn test_unset() {
+        assert_eq!(
+            run("export FOO=bar; unset FOO\necho [$FOO]; sh -c 'echo [$FOO]'\n"),
+            "[]\n[]\n"
+        );
+    }
+
+    #[test]
+    fn test_readonly() {
+        assert_eq!(
+            run("readonly FOO=1\nFOO=2 || echo failed\nunset FOO; echo $FOO $?\n"),
+            "failed\n1 1\n"
+        );
+    }
+
+    #[test]
+    fn test_prefix_assignment_only_affects_child() {
+        assert_eq!(
+            run("FOO=outer\nFOO=inner sh -c 'echo $FOO'\necho $FOO\n"),
+            "inner\nouter\n"
+        );
+    }
+
+    #[test]
+    fn test_prefix_assignment_for_builtins() {
+        assert_eq!(
+            run("HOME=/nowhere; HOME=/tmp cd; pwd; echo $HOME\nx=1 echo $x; echo [$x]\n"),
+            "/tmp\n/nowhere\n\n[]\n"
+        );
+        assert_eq!(
+            run("unset FOO; FOO=1 export FOO; sh -c 'echo $FOO'; echo $FOO\n"),
+            "1\n1\n"
+        );
+    }
+
+    #[test]
+    fn test_pipeline_stages_run_in_subshells() {
+        assert_eq!(
+            run("cd /tmp; cd / | cat; pwd\nx=1; { x=2; echo $x; } | cat; echo $x\n"),
+            "/tmp\n2\n1\n"
+        );
+    }
+
+    #[test]
+    fn test_assignment_in_pipeline_is_not_kept() {
+        assert_eq!(
+            run("x=1 | cat; echo [$x]\ny=2; y=3 | cat; echo $y\n"),
+            "[]\n2\n"
+        );
+    }
+
+    #[test]
+    fn test_subshell_variables_are_restored() {
+        assert_eq!(run("FOO=a; (FOO=b; echo $FOO); echo $FOO\n"), "b\na\n");
+    }
+
+    #[test]
+    fn test_parameter_error_respects_stderr_redirection() {
+        let dir = std::env::temp_dir().join(format!("shell_test_err_{}", std::process::id()));
+        let path = dir.display();
+        assert_eq!(
+            run(&format!(
+                "{{ echo ${{NOPE:?must be set}}; }} 2>{path}; echo $?; cat {path}; rm {path}\n"
+            )),
+            "1\nNOPE: must be set\n"
+        );
+    }
+
+    #[test]
+    fn test_command_substitution() {
+        assert_eq!(run("echo $(echo hi)-`echo there`\n"), "hi-there\n");
+    }
+
+    #[test]
+    fn test_command_substitution_strips_trailing_newlines() {
+        assert_eq!(
+            run("x=$(printf 'a\\nb\\n\\n'); echo \"[$x]\"\n"),
+            "[a\nb]\n"
+        );
+    }
+
+    #[test]
+    fn test_command_substitution_field_splitting() {
+        assert_eq!(
+            run("printf '<%s>' $(echo a b) \"$(echo 'c  d')\"; echo\n"),
+            "<a><b><c  d>\n"
+        );
+    }
+
+    #[test]
+    fn test_nested_command_substitution() {
+        assert_eq!(
+            run("echo $(echo $(echo deep)) `echo \\`echo inner\\``\n"),
+            "deep inner\n"
+        );
+    }
+
+    #[test]
+    fn test_command_substitution_quoting() {
+        assert_eq!(run("echo \"$(echo \")\" 'x y')\"\n"), ") x y\n");
+    }
+
+    #[test]
+    fn test_command_substitution_status() {
+        assert_eq!(run("x=$(false); echo $?\nx=$(true); echo $?\n"), "1\n0\n");
+    }
+
+    #[test]
+    fn test_command_substitution_runs_in_subshell() {
+        assert_eq!(run("y=1; z=$(y=2; echo $y); echo $y $z\n"), "1 2\n");
+    }
+
+    #[test]
+    fn test_exit_in_command_substitution() {
+        assert_eq!(
+            run("echo $(exit 4) after; echo [$(exit 4)] $?\nx=$(echo out; exit 3); echo $? $x\n"),
+            "after\n[] 4\n3 out\n"
+        );
+    }
+
+    #[test]
+    fn test_arithmetic_expansion() {
+        assert_eq!(
+            run("x=5; echo $((x + 1)) $(( $x * 2 )) \"$((x << 1))\" $((x++)) $x\n"),
+            "6 10 10 5 6\n"
+        );
+    }
+
+    #[test]
+    fn test_arithmetic_command_status() {
+        assert_eq!(
+            run("((2 > 1)); echo $?; ((0)); echo $?; ((i = 3)); echo $i\n"),
+            "0\n1\n3\n"
+        );
+    }
+
+    #[test]
+    fn test_let() {
+        assert_eq!(
+            run("let a=1+2 'b = a * 2'; echo $a $b $?; let 0; echo $?\n"),
+            "3 6 0\n1\n"
+        );
+    }
+
+    #[test]
+    fn test_arithmetic_division_by_zero() {
+        assert_eq!(
+            run("echo $((1/0)); echo $?; ((1/0)); echo $?; let 1/0; echo $?\n"),
+            "1\n1\n1\n"
+        );
+    }
+
+    #[test]
+    fn test_subshell_in_command_substitution_is_not_arithmetic() {
+        assert_eq!(
+            run("echo $( (echo sub) )\n((echo nested) )\n"),
+            "sub\nnested\n"
+        );
+    }
+
+    #[test]
+    fn test_pathname_expansion() {
+        let dir = std::env::temp_dir().join(format!("shell-glob-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        for file in ["b.rs", "a.rs", ".hidden.rs", "c.txt"] {
+            std::fs::write(dir.join(file), "").unwrap();
+        }
+        let script = format!(
+            "cd {}\necho *.rs\necho .*.rs\necho '*.rs' \\*.rs \"*\"\nx='*.txt'; echo $x \"$x\"\n",
+            dir.display()
+        );
+        assert_eq!(
+            run(&script),
+            "a.rs b.rs\n.hidden.rs\n*.rs *.rs *\nc.txt *.txt\n"
+        );
+    }
+
+    #[test]
+    fn test_unmatched_patterns() {
+        assert_eq!(
+            run("echo /nonexistent/*.x\nshopt -s nullglob; echo /nonexistent/*.x end\n"),
+            "/nonexistent/*.x\nend\n"
+        );
+        assert_eq!(
+            run("shopt -s failglob; echo /nonexistent/*.x; echo $?\n"),
+            "1\n"
+        );
+    }
+
+    #[test]
+    fn test_shopt() {
+        assert_eq!(
+            run(
+                "shopt -s nullglob; shopt nullglob failglob; echo $?; shopt -q nullglob; echo $?\n"
+            ),
+            "nullglob       \ton\nfailglob       \toff\n1\n0\n"
+        );
+    }
+
+    #[test]
+    fn test_extglob_and_globstar_options() {
+        let dir = std::env::temp_dir().join(format!("shell-glob-options-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("sub")).unwrap();
+        std::fs::write(dir.join("a.rs"), "").unwrap();
+        std::fs::write(dir.join("sub/b.rs"), "").unwrap();
+        let script = format!(
+            "cd {}\n\
+             echo **/*.rs; x=a.rs; echo ${{x/@(a|b)/c}}\n\
+             shopt -s extglob globstar\n\
+             echo **/*.rs; echo ${{x/@(a|b)/c}} !(sub)\n",
+            dir.display()
+        );
+        assert_eq!(run(&script), "sub/b.rs\na.rs\na.rs sub/b.rs\nc.rs a.rs\n");
+    }
+
+    #[test]
+    fn test_redirection_target_pattern() {
+        let dir = std::env::temp_dir().join(format!("shell-redirect-glob-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("sub")).unwrap();
+        std::fs::write(dir.join("sub/only.log"), "").unwrap();
+        let script = format!(
+            "shopt -s globstar; cd {}\necho hi > **/*.log; cat sub/only.log\necho no > *; echo $?\n",
+            dir.display()
+        );
+        assert_eq!(run(&script), "hi\n1\n");
+    }
+
+    #[test]
+    fn test_brace_expansion() {
+        assert_eq!(
+            run("x=v; echo ${x}{1,2} $x{1,2} file{1..3}.txt '{a,b}' {a,b}$(echo c)\n"),
+            "v1 v2 file1.txt file2.txt file3.txt {a,b} ac bc\n"
+        );
+    }
+
+    #[test]
+    fn test_tilde_expansion() {
+        let dir = std::env::temp_dir().join(format!("shell-tilde-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let script = format!(
+            "HOME={}; cd ~; pwd; echo hi > ~/out; cat out\ncd /; echo ~- ~+; P=/x:~; echo $P\n",
+            dir.display()
+        );
+        let dir = dir.display();
+        assert_eq!(run(&script), format!("{dir}\nhi\n{dir} /\n/x:{dir}\n"));
+    }
+
+    fn temp_file(name: &str, contents: &str) -> String {
+        let path = std::env::temp_dir().join(format!("{}-{}", name, std::process::id()));
+        std::fs::write(&path, contents).unwrap();
+        path.display().to_string()
+    }
+
+    #[test]
+    fn test_input_redirection() {
+        let data = temp_file("shell-input", "b\na\nc\n");
+        assert_eq!(
+            run(&format!("sort < {data}\ncat 0<{data} | head -n 1\n")),
+            "a\nb\nc\nb\n"
+        );
+    }
+
+    #[test]
+    fn test_missing_input_file() {
+        assert_eq!(
+            run("cat < /nonexistent/input; echo $?\nread x < /nonexistent/input; echo $?\n"),
+            "1\n1\n"
+        );
+        let err = temp_file("shell-input-err", "");
+        assert_eq!(
+            run(&format!("cat 2> {err} < /nonexistent/input; cat {err}\n")),
+            "/nonexistent/input: No such file or directory\n"
+        );
+    }
+
+    #[test]
+    fn test_read_write_redirection() {
+        let path = std::env::temp_dir().join(format!("shell-rw-{}", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let path = path.display();
+        assert_eq!(
+            run(&format!("cat <> {path}; echo $?; ls {path}\n")),
+            format!("0\n{path}\n")
+        );
+    }
+
+    #[test]
+    fn test_read() {
+        let data = temp_file("shell-read", "one two  three \n  x\\\ny\\ z\nlast");
+        let script = format!(
+            "{{ read a b; read -r c; read d; read; echo \"[$a][$b][$c][$d][$REPLY]\" $?; }} < {data}\n\
+             read e < /dev/null; echo $?\n"
+        );
+        assert_eq!(run(&script), "[one][two  three][x\\][y z][last] 0\n1\n");
+    }
+
+    #[test]
+    fn test_redirection_order() {
+        let out = temp_file("shell-order-out", "");
+        let script = format!(
+            "ls /nonexistent/dir >{out} 2>&1; cat {out}\n\
+             ls /nonexistent/dir 2>&1 >{out} | wc -l; wc -c < {out}\n"
+        );
+        assert_eq!(
+            run(&script),
+            "ls: cannot access '/nonexistent/dir': No such file or directory\n1\n0\n"
+        );
+    }
+
+    #[test]
+    fn test_fd_duplication() {
+        assert_eq!(
+            run("echo out >&2 2>/dev/null | wc -c\n{ echo a; echo b >&2; } 2>&1 | wc -l\n"),
+            "0\n2\n"
+        );
+    }
+
+    #[test]
+    fn test_arbitrary_fds() {
+        let file = temp_file("shell-fd3", "");
+        assert_eq!(
+            run(&format!(
+                "echo three 3>{file} >&3; cat {file}\nsh -c 'echo four >&4' 4>&1\nsh -c 'cat <&5' 5<{file}\n"
+            )),
+            "three\nfour\nthree\n"
+        );
+    }
+
+    #[test]
+    fn test_fd_closing() {
+        assert_eq!(
+            run("echo closed >&-; echo $?\ncat <&- 2>/dev/null; echo $?\necho x >&9; echo $?\n"),
+            "1\n1\n1\n"
+        );
+    }
+
+    #[test]
+    fn test_closed_stderr_does_not_abort_pipeline() {
+        assert_eq!(
+            run("cat 2>&- < /nonexistent; echo $?; echo after\n"),
+            "1\nafter\n"
+        );
+    }
+
+    #[test]
+    fn test_redirections_anywhere() {
+        let out = temp_file("shell-anywhere-out", "");
+        let err = temp_file("shell-anywhere-err", "");
+        let script = format!(
+            "echo > {out} hello world; cat {out}\n\
+             ls /nonexistent/dir > {out} 2> {err}; wc -c < {out}; wc -l < {err}\n\
+             2>{err} >{out} echo front; cat {out}\n\
+             echo '>' \"2>\" x\n\
+             echo old > {out}; > {out}; wc -c < {out}\n"
+        );
+        assert_eq!(run(&script), "hello world\n0\n1\nfront\n> 2> x\n0\n");
+    }
+
+    #[test]
+    fn test_here_documents() {
+        let script = "x=world\n\
+                      cat <<EOF\n\
+                      hello $x $((1 + 2)) \\$x \"q\" 'q'\n\
+                      EOF\n\
+                      cat <<'EOF'\n\
+                      hello $x\n\
+                      EOF\n\
+                      cat <<-END | tr a-z A-Z\n\
+                      \t\ttabs $x\n\
+                      \tEND\n\
+                      read a b <<EOF; echo \"$a|$b\"\n\
+                      one two three\n\
+                      EOF\n\
+                      cat 3<<EOF <&3\n\
+                      fd three\n\
+                      EOF\n";
+        assert_eq!(
+            run(script),
+            "hello world 3 $x \"q\" 'q'\nhello $x\nTABS WORLD\none|two three\nfd three\n"
+        );
+    }
+
+    #[test]
+    fn test_here_document_ended_by_end_of_input() {
+        assert_eq!(run("cat <<EOF\nfirst\nsecond"), "first\nsecond\n");
+    }
+
+    #[test]
+    fn test_here_strings() {
+        let script = "v='a  b'\n\
+                      cat <<< \"$v\"\n\
+                      tr a-z A-Z <<<$v\n\
+                      read x y <<< '1 2 3'; echo \"$x|$y\"\n\
+                      wc -l <<< ''\n\
+                      cat 4<<<fd <&4\n";
+        assert_eq!(run(script), "a  b\nA  B\n1|2 3\n1\nfd\n");
+        assert_eq!(
+            run("v=$(head -c 100000 /dev/zero | tr '\\0' x); wc -c <<< \"$v\"\n"),
+            "100001\n"
+        );
+    }
+
+    #[test]
+    fn test_process_substitution() {
+        let a = temp_file("shell-procsub-a", "b\na\nc\n");
+        let b = temp_file("shell-procsub-b", "c\nb\na\n");
+        let script = format!(
+            "diff <(sort {a}) <(sort {b}) && echo same\n\
+             cat <(echo one) <(echo two)\n\
+             echo hello > >(tr a-z A-Z); echo after\n\
+             read x < <(echo line); echo \"$x\"\n\
+             {{ cat; }} < <(echo group)\n\
+             head -1 <(yes)\n\
+             echo <(true) | grep -c '^/dev/fd/[0-9]*$'\n"
+        );
+        assert_eq!(
+            run(&script),
+            "same\none\ntwo\nHELLO\nafter\nline\ngroup\ny\n1\n"
+        );
+    }
+
+    #[test]
+    fn test_process_substitution_in_pipeline_ends() {
+        assert_eq!(
+            run("cat <(yes) | head -1; echo after\ncat < <(yes) | head -1\n"),
+            "y\nafter\ny\n"
+        );
+    }
+
+    #[test]
+    fn test_process_substitution_runs_in_subshell() {
+        assert_eq!(
+            run("cd /tmp; cat <(cd /; echo in); pwd\n\
+                 x=1; cat <(x=5; echo in); echo [$x]\n\
+                 cat <(exit 9); echo alive\n"),
+            "in\n/tmp\nin\n[1]\nalive\n"
+        );
+    }
+
+    #[test]
+    fn test_noclobber() {
+        let file = temp_file("shell-noclobber", "kept\n");
+        let script = format!(
+            "set -o noclobber\n\
+             echo lost > {file}; echo $?; cat {file}\n\
+             echo lost &> {file}; cat {file}\n\
+             echo appended >> {file}; cat {file}\n\
+             echo forced >| {file}; cat {file}\n\
+             echo fine > /dev/null; echo $?\n\
+             set +C\n\
+             echo plain > {file}; cat {file}\n\
+             set -C; set -o\n\
+             set +o noclobber; set +o\n"
+        );
+        assert_eq!(
+            run(&script),
+            "1\nkept\nkept\nkept\nappended\nforced\n0\nplain\n\
+             multios        \toff\nnoclobber      \ton\n\
+             set +o multios\nset +o noclobber\n"
+        );
+    }
+
+    #[test]
+    fn test_subshell_options_are_not_kept() {
+        let file = temp_file("shell-subshell-options", "kept\n");
+        let script = format!(
+            "(set -o noclobber; echo lost > {file}); cat {file}\n\
+             (shopt -s nullglob; echo /nonexistent/*.x end)\n\
+             echo plain > {file}; cat {file}\n\
+             shopt nullglob; echo /nonexistent/*.x\n"
+        );
+        assert_eq!(
+            run(&script),
+            "kept\nend\nplain\nnullglob       \toff\n/nonexistent/*.x\n"
+        );
+    }
+
+    #[test]
+    fn test_redirection_does_not_create_directories() {
+        let dir = std::env::temp_dir().join(format!("shell-no-mkdir-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let dir = dir.display();
+        let script = format!(
+            "echo x > {dir}/sub/file; echo $?\n\
+             echo x >> {dir}/file; echo $?\n\
+             {{ echo x; }} &> {dir}/file; echo $?\n\
+             history -w {dir}/history; echo $?\n\
+             ls {dir} 2> /dev/null || echo missing\n"
+        );
+        assert_eq!(run(&script), "1\n1\n1\n1\nmissing\n");
+    }
+
+    #[test]
+    fn test_multios() {
+        let a = temp_file("shell-multios-a", "");
+        let b = temp_file("shell-multios-b", "");
+        let script = format!(
+            "echo off > {a} > {b}; cat {a} {b}\n\
+             set -o multios\n\
+             echo on > {a} > {b}; cat {a} {b}\n\
+             echo piped > {a} | tr a-z A-Z; cat {a}\n\
+             {{ echo inner > {a}; }} | wc -l\n\
+             {{ echo x; echo y; }} > {a} >> {b}; cat {a} {b}\n\
+             seq 100000 > {a} > {b} | tail -1; wc -l < {a}\n\
+             yes > {a} | head -1\n"
+        );
+        assert_eq!(
+            run(&script),
+            "off\non\non\nPIPED\npiped\n0\nx\ny\non\nx\ny\n100000\n100000\ny\n"
+        );
+    }
+
+    #[test]
+    fn test_exec_redirections() {
+        let file = temp_file("shell-exec", "");
+        let script = format!(
+            "exec 4>{file}\n\
+             echo one >&4\n\
+             echo two >&4\n\
+             cat {file}\n\
+             exec 4>&-\n\
+             echo three >&4 || echo closed\n\
+             exec echo replaced\n\
+             echo not reached\n"
+        );
+        assert_eq!(run(&script), "one\ntwo\nclosed\nreplaced\n");
+    }
+
+    #[test]
+    fn test_exec_in_subshell() {
+        let file = temp_file("shell-exec-subshell", "");
+        let script = format!(
+            "(exec echo hi); echo after\n\
+             (exec 4>{file})\n\
+             echo outside >&4 || echo closed\n"
+        );
+        assert_eq!(run(&script), "hi\nafter\nclosed\n");
+    }
+
+    #[test]
+    fn test_exec_on_same_line() {
+        let file = temp_file("shell-exec-line", "");
+        let inner = temp_file("shell-exec-line-inner", "");
+        let script = format!(
+            "exec 5>{file}; echo same >&5 && echo and >&5; cat {file}\n\
+             (exec 4>{inner}; echo inside >&4); cat {inner}\n\
+             exec 5>&-; echo gone >&5 || echo closed\n"
+        );
+        assert_eq!(run(&script), "same\nand\ninside\nclosed\n");
+    }
+
+    #[test]
+    fn test_tcp_redirection() {
+        use std::io::{BufRead, BufReader};
+        use std::net::TcpListener;
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server = std::thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut line = String::new();
+            BufReader::new(&stream).read_line(&mut line).unwrap();
+            write!(&stream, "pong {}", line).unwrap();
+        });
+        let script = format!(
+            "exec 3<>/dev/tcp/127.0.0.1/{port}\n\
+             echo ping >&3\n\
+             cat <&3\n\
+             echo x > /dev/tcp/127.0.0.1/notaport || echo failed\n"
+        );
+        assert_eq!(run(&script), "pong ping\nfailed\n");
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn test_udp_redirection() {
+        let socket = std::net::UdpSocket::bind("127.0.0.1:0").unwrap();
+        let port = socket.local_addr().unwrap().port();
+        run(&format!("echo datagram > /dev/udp/127.0.0.1/{port}\n"));
+        let mut buf = [0; 64];
+        let len = socket.recv(&mut buf).unwrap();
+        assert_eq!(&buf[..len], b"datagram\n");
+    }
+
+    #[test]
+    fn test_pipe_stdout_and_stderr() {
+        let script = "ls /nonexistent |& wc -l\n\
+                      cd /nonexistent |& wc -l\n\
+                      { echo out; cd /nonexistent; } |& wc -l\n\
+                      ls /nonexistent 2> /dev/null |& wc -l\n\
+                      ls /nonexistent | wc -l\n";
+        assert_eq!(run(script), "1\n1\n2\n1\n0\n");
+    }
+
+    #[test]
+    fn test_background_jobs() {
+        // The jobs wait for the lock file to go, and the shell then waits for
+        // them to finish, so no step depends on how long anything takes.
+        let lock = temp_file("shell-jobs-lock", "");
+        let gate = temp_file(
+            "shell-jobs-gate",
+            &format!("while [ -e {lock} ]; do sleep 0.01; done\n"),
+        );
+        let wait = temp_file(
+            "shell-jobs-wait",
+            "for p; do\n\
+             while [ -e /proc/$p ] && ! grep -qs '^State:.Z' /proc/$p/status; do sleep 0.01; done\n\
+             done\n",
+        );
+        let script = format!(
+            "sh {gate} &\n\
+             echo $? $!; p1=$!\n\
+             {{ sh {gate}; false; }} &\n\
+             p2=$!\n\
+             sh {gate} && cat &\n\
+             p3=$!\n\
+             rm {lock}; sh {wait} $p1 $p2 $p3\n\
+             echo a && echo b &\n\
+             sh {wait} $!\n\
+             echo done\n"
+        );
+        let (stdout, stderr) = run_with_stderr(&script);
+        let lines: Vec<&str> = stdout.lines().collect();
+        let job_lines: Vec<&str> = stderr.lines().filter(|l| l.starts_with('[')).collect();
+        let pid = job_lines[0].strip_prefix("[1] ").unwrap();
+        assert_eq!(
+            lines,
+            [
+                "0 ".to_string() + pid,
+                "a".into(),
+                "b".into(),
+                "done".into()
+            ]
+        );
+        assert!(job_lines[1].starts_with("[2] "));
+        assert!(job_lines[2].starts_with("[3] "));
+        assert_eq!(
+            job_lines[3..6],
+            [
+                format!("[1]   Done                    sh {gate}"),
+                format!("[2]-  Exit 1                  {{ sh {gate}; false; }}"),
+                format!("[3]+  Done                    sh {gate} && cat"),
+            ]
+        );
+        assert!(job_lines[6].starts_with("[1] "));
+        assert_eq!(
+            job_lines[7],
+            "[1]+  Done                    echo a && echo b"
+        );
+    }
+
+    #[test]
+    fn test_jobs_and_wait() {
+        // The jobs run until the lock file goes, so `jobs` sees them running,
+        // and `wait` is what lets the script go on once they finish.
+        let lock = temp_file("shell-wait-lock", "");
+        let gate = temp_file(
+            "shell-wait-gate",
+            &format!("while [ -e {lock} ]; do sleep 0.01; done\n"),
+        );
+        let script = format!(
+            "sh {gate} &\n\
+             p1=$!\n\
+             {{ sh {gate}; exit 3; }} &\n\
+             jobs\n\
+             rm {lock}\n\
+             wait %2; echo $?\n\
+             wait $p1; echo $?\n\
+             jobs\n\
+             (exit 4) &\n\
+             false &\n\
+             wait; echo $?\n\
+             wait %1; echo $?\n\
+             wait 1; echo $?\n"
+        );
+        let (stdout, stderr) = run_with_stderr(&script);
+        assert_eq!(
+            stdout,
+            format!(
+                "[1]-  Running                 sh {gate} &\n\
+                 [2]+  Running                 {{ sh {gate}; exit 3; }} &\n\
+                 3\n0\n0\n127\n127\n"
+            )
+        );
+        let errors: Vec<&str> = stderr.lines().filter(|l| l.starts_with("wait:")).collect();
+        assert_eq!(
+            errors,
+            [
+                "wait: %1: no such job",
+                "wait: pid 1 is not a child of this shell"
+            ]
+        );
+    }
+}